@@ -0,0 +1,9 @@
+fn main() {
+    let input = std::env::args().nth(1).unwrap();
+    let document = std::fs::read_to_string(&input).unwrap();
+
+    let mut parser = tap_parser::TapParser::new();
+    let document = parser.parse(&document).unwrap();
+
+    print!("{}", tap_parser::junit(&input, &document.statements));
+}
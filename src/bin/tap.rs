@@ -0,0 +1,274 @@
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Command as Process, ExitCode},
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use tap_parser::{Harness, TapParser, TapStatement, TapSummary, TapTest};
+
+/// Exit code of a run that failed, or bailed out
+const FAILURE: u8 = 1;
+/// Exit code of a document that could not be read or parsed
+const INVALID: u8 = 2;
+
+#[derive(Parser)]
+#[command(version, about = "Inspect and convert TAP documents")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Checks the syntax and the consistency of a document, and reports all the problems found
+    Validate(Input),
+    /// Counts the results of the tests
+    Summary(Input),
+    /// Converts a document to another format
+    Convert {
+        #[arg(long, value_enum)]
+        to: Format,
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Prints the tests as a tree of subtests
+    Tree(Input),
+    /// Runs test programs, and reports their results
+    Run {
+        /// Kills the test programs still running after this number of seconds
+        #[arg(long)]
+        timeout: Option<f64>,
+        /// Number of test programs to run at the same time
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+        /// Test programs to run
+        #[arg(required = true)]
+        programs: Vec<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct Input {
+    /// File containing the TAP document, reads the standard input if missing or `-`
+    file: Option<PathBuf>,
+    /// Looks for the document inside log output, and strips the prefix of its lines
+    #[arg(long)]
+    extract: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Junit,
+    Tap,
+}
+
+impl Input {
+    fn name(&self) -> String {
+        match &self.file {
+            Some(path) if path.as_os_str() != "-" => path.display().to_string(),
+            _ => "<stdin>".into(),
+        }
+    }
+
+    fn parser<'a>(&self) -> TapParser<'a> {
+        if self.extract {
+            TapParser::new().with_extraction()
+        } else {
+            TapParser::new()
+        }
+    }
+
+    fn read(&self) -> Result<String, String> {
+        let mut document = String::new();
+        let res = match &self.file {
+            Some(path) if path.as_os_str() != "-" => {
+                std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut document))
+            }
+            _ => std::io::stdin().read_to_string(&mut document),
+        };
+
+        match res {
+            Ok(_) => Ok(document),
+            Err(e) => Err(format!("{}: {e}", self.name())),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let input = match &args.command {
+        Command::Validate(input)
+        | Command::Summary(input)
+        | Command::Tree(input)
+        | Command::Convert { input, .. } => input,
+        Command::Run {
+            timeout,
+            jobs,
+            programs,
+        } => return run(*timeout, *jobs, programs),
+    };
+
+    let document = match input.read() {
+        Ok(document) => document,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(INVALID);
+        }
+    };
+
+    if let Command::Validate(input) = &args.command {
+        return validate(input, &document);
+    }
+
+    let statements = match input.parser().parse(&document) {
+        Ok(document) => document.statements,
+        Err(e) => {
+            eprintln!("{}: {e}", input.name());
+            return ExitCode::from(INVALID);
+        }
+    };
+    let summary = TapSummary::new(&statements);
+
+    match &args.command {
+        Command::Validate(_) | Command::Run { .. } => unreachable!(),
+        Command::Summary(_) => print_summary(&summary),
+        Command::Convert { to, input } => match to {
+            Format::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&statements).expect("Statements are valid JSON")
+            ),
+            Format::Junit => print!("{}", tap_parser::junit(&input.name(), &statements)),
+            Format::Tap => print!("{}", tap_parser::write(&statements)),
+        },
+        Command::Tree(input) => {
+            println!("{}", input.name());
+            print_tree(&statements, "");
+        }
+    }
+
+    if summary.is_success() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(FAILURE)
+    }
+}
+
+fn run(timeout: Option<f64>, jobs: usize, programs: &[PathBuf]) -> ExitCode {
+    let mut harness = Harness::new().with_jobs(jobs);
+    if let Some(timeout) = timeout {
+        harness = harness.with_timeout(Duration::from_secs_f64(timeout));
+    }
+
+    let report = harness.run_all(
+        programs
+            .iter()
+            .map(|program| (program.display().to_string(), Process::new(program))),
+    );
+    print!("{report}");
+
+    if report.is_success() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(FAILURE)
+    }
+}
+
+fn validate(input: &Input, document: &str) -> ExitCode {
+    let name = input.name();
+    let (document, errors) = input.parser().parse_recovering(document);
+    let findings = tap_parser::validate(&document.statements);
+
+    for error in &errors {
+        eprintln!("{name}: {error}");
+    }
+    for finding in &findings {
+        eprintln!("{name}: {finding}");
+    }
+
+    if !errors.is_empty() {
+        ExitCode::from(INVALID)
+    } else if !findings.is_empty() || !TapSummary::new(&document.statements).is_success() {
+        ExitCode::from(FAILURE)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn print_summary(summary: &TapSummary) {
+    let planned = summary
+        .planned
+        .map_or_else(|| "no plan".to_string(), |count| count.to_string());
+    println!("tests:       {} ({planned} planned)", summary.total());
+    println!("passed:      {}", summary.passed);
+    println!("failed:      {}", summary.failed);
+    println!("skipped:     {}", summary.skipped);
+    println!("todo:        {}", summary.todo);
+    println!("todo passed: {}", summary.todo_passed);
+
+    let result = if summary.bailed {
+        "BAILED OUT"
+    } else if summary.is_success() {
+        "PASS"
+    } else {
+        "FAIL"
+    };
+    println!("result:      {result}");
+}
+
+fn test_line(test: &TapTest) -> String {
+    let mut line = if test.result { "ok" } else { "not ok" }.to_string();
+    if let Some(number) = test.number {
+        line += &format!(" {number}");
+    }
+    if let Some(desc) = test.unescaped_desc() {
+        line += &format!(" - {desc}");
+    }
+    if let Some(directive) = &test.directive {
+        line += &format!(" # {:?}", directive.kind).to_uppercase();
+        if let Some(reason) = directive.unescaped_reason() {
+            line += &format!(" {reason}");
+        }
+    }
+    line
+}
+
+fn print_tree(statements: &[TapStatement], prefix: &str) {
+    let nodes: Vec<_> = statements
+        .iter()
+        .filter(|s| {
+            matches!(
+                s,
+                TapStatement::TestPoint(_) | TapStatement::Subtest(_) | TapStatement::BailOut(_)
+            )
+        })
+        .collect();
+
+    for (idx, node) in nodes.iter().enumerate() {
+        let (branch, indent) = if idx + 1 == nodes.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        match node {
+            TapStatement::TestPoint(test) => println!("{prefix}{branch}{}", test_line(test)),
+            TapStatement::Subtest(subtest) => {
+                let line = match &subtest.ending {
+                    Some(ending) => test_line(ending),
+                    None => subtest.name.as_deref().unwrap_or("subtest").to_string(),
+                };
+                println!("{prefix}{branch}{line}");
+                print_tree(&subtest.body.statements, &format!("{prefix}{indent}"));
+            }
+            TapStatement::BailOut(bail_out) => match &bail_out.reason {
+                Some(reason) => println!("{prefix}{branch}Bail out! {reason}"),
+                None => println!("{prefix}{branch}Bail out!"),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
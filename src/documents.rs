@@ -0,0 +1,93 @@
+use crate::{read_version, LocatedError, TapDocument, TapParser};
+
+///
+/// A document of an input containing several documents. See [parse_documents].
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParsedDocument<'a> {
+    /// Text between the end of the previous document, or the start of the input, and the version
+    /// line of this document
+    pub leading: &'a str,
+    /// The document, or the error that stopped its parsing
+    pub result: Result<TapDocument<'a>, LocatedError>,
+}
+
+///
+/// Parses an input containing several documents back to back, such as an archived log of
+/// several test runs.
+///
+/// Each document starts with its own `TAP version` or `KTAP version` line, and ends once all its
+/// tests were read, or at the version line of the next document. The text outside of the
+/// documents is kept: before each document in [ParsedDocument::leading], and after the last one
+/// in the returned text. Once a document failed to parse, its following lines are part of the
+/// text leading to the next document.
+///
+/// The [spans](crate::Span) of the statements are positions in the whole input.
+///
+/// ```rust
+/// use tap_parser::parse_documents;
+///
+/// let input = "run 1\nTAP version 14\n1..1\nok 1\nrun 2\nTAP version 14\n1..0\ndone\n";
+/// let (documents, trailing) = parse_documents(input);
+///
+/// assert_eq!(documents.len(), 2);
+/// assert_eq!(documents[0].leading, "run 1\n");
+/// assert_eq!(documents[1].leading, "run 2\n");
+/// assert_eq!(documents[1].result.as_ref().unwrap().statements[0].span().line, 7);
+/// assert_eq!(trailing, "done\n");
+/// ```
+///
+pub fn parse_documents(input: &str) -> (Vec<ParsedDocument<'_>>, &str) {
+    let mut documents = Vec::new();
+    // Start of the text that is not part of a document
+    let mut outside = 0;
+    // The same parser reads all the documents, reset at each version line
+    let mut parser = TapParser::new();
+    // Text leading to the document being parsed, if any
+    let mut current: Option<&str> = None;
+
+    let mut offset = 0;
+    for (idx, line) in input.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+
+        if read_version(line).is_some() {
+            if let Some(leading) = current.take() {
+                let result = parser.finish_document(Vec::new());
+                documents.push(ParsedDocument { leading, result });
+                outside = start;
+            }
+
+            parser.reset();
+            parser.lines_read = idx;
+            parser.offset = start;
+            current = Some(&input[outside..start]);
+        }
+
+        let Some(leading) = current else {
+            continue;
+        };
+
+        if parser.ends_before(line) {
+            let result = parser.finish_document(Vec::new());
+            documents.push(ParsedDocument { leading, result });
+            current = None;
+            outside = start;
+        } else if let Err(e) = parser.feed_line(line) {
+            documents.push(ParsedDocument {
+                leading,
+                result: Err(e),
+            });
+            current = None;
+            outside = offset;
+        }
+    }
+
+    if let Some(leading) = current {
+        let result = parser.finish_document(Vec::new());
+        documents.push(ParsedDocument { leading, result });
+        outside = input.len();
+    }
+
+    (documents, &input[outside..])
+}
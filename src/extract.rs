@@ -0,0 +1,183 @@
+///
+/// Decoration added before every line of a TAP document by the tool that logged it, such as the
+/// timestamp of a kernel console (`[   12.345678] ok 1 - foo`) or the header of a container log
+/// (`2026-10-16T10:00:00Z stdout F ok 1`).
+///
+/// Used by [TapParser::with_prefix](crate::TapParser::with_prefix) to strip the prefix of each
+/// line before parsing it. Lines that don't start with the prefix are not part of the document.
+///
+pub struct LinePrefix {
+    matcher: Matcher,
+}
+
+/// Returns the length of the prefix of a line
+type CustomMatcher = Box<dyn Fn(&str) -> Option<usize> + Send + Sync>;
+
+enum Matcher {
+    Literal(String),
+    Shape(Vec<Token>),
+    Custom(CustomMatcher),
+}
+
+enum Token {
+    /// Any number, with or without a decimal part
+    Number,
+    /// Any number of spaces, possibly none
+    Spaces,
+    Char(char),
+}
+
+impl LinePrefix {
+    ///
+    /// Matches lines starting with exactly `prefix`.
+    ///
+    pub fn literal(prefix: impl Into<String>) -> Self {
+        Self {
+            matcher: Matcher::Literal(prefix.into()),
+        }
+    }
+
+    ///
+    /// Matches lines starting with text shaped like `sample`: each number of the sample matches
+    /// any number, with or without a decimal part, and each run of spaces any padding. Spaces
+    /// ending the sample must match exactly, so that the indentation of subtests is kept.
+    ///
+    /// ```rust
+    /// use tap_parser::LinePrefix;
+    ///
+    /// let prefix = LinePrefix::like("[   12.345678] ");
+    /// assert_eq!(prefix.strip("[  123.000001]     ok 1"), Some("    ok 1"));
+    /// assert_eq!(prefix.strip("ok 1"), None);
+    /// ```
+    ///
+    pub fn like(sample: &str) -> Self {
+        let trailing = sample.len() - sample.trim_end_matches(' ').len();
+        let (body, trailing) = sample.split_at(sample.len() - trailing);
+
+        let mut tokens = Vec::new();
+        let mut rest = body;
+        while let Some(c) = rest.chars().next() {
+            let (token, stripped) = match c {
+                '0'..='9' => (Token::Number, strip_number(rest)),
+                ' ' => (Token::Spaces, rest.trim_start_matches(' ')),
+                c => (Token::Char(c), &rest[c.len_utf8()..]),
+            };
+            tokens.push(token);
+            rest = stripped;
+        }
+        tokens.extend(trailing.chars().map(Token::Char));
+
+        Self {
+            matcher: Matcher::Shape(tokens),
+        }
+    }
+
+    ///
+    /// Matches lines with a function returning the length in bytes of the prefix of a line, or
+    /// `None` if the line does not start with the prefix.
+    ///
+    pub fn new(matcher: impl Fn(&str) -> Option<usize> + Send + Sync + 'static) -> Self {
+        Self {
+            matcher: Matcher::Custom(Box::new(matcher)),
+        }
+    }
+
+    ///
+    /// Returns the line without its prefix, or `None` if the line does not start with the prefix.
+    ///
+    pub fn strip<'l>(&self, line: &'l str) -> Option<&'l str> {
+        match &self.matcher {
+            Matcher::Literal(prefix) => line.strip_prefix(prefix.as_str()),
+            Matcher::Shape(tokens) => {
+                let mut rest = line;
+                for token in tokens {
+                    rest = match token {
+                        Token::Number => {
+                            let stripped = strip_number(rest);
+                            if stripped.len() == rest.len() {
+                                return None;
+                            }
+                            stripped
+                        }
+                        Token::Spaces => rest.trim_start_matches(' '),
+                        Token::Char(c) => rest.strip_prefix(*c)?,
+                    };
+                }
+                Some(rest)
+            }
+            Matcher::Custom(matcher) => line.get(matcher(line)?..),
+        }
+    }
+}
+
+/// Locates a document inside log output, and strips the prefix of its lines
+pub(crate) struct Extractor {
+    prefix: Option<LinePrefix>,
+    /// Whether the prefix is learned from the version line of each document
+    learn: bool,
+    found: bool,
+}
+
+impl Extractor {
+    /// Extracts a document whose prefix is learned from its version line
+    pub(crate) fn new() -> Self {
+        Self {
+            prefix: None,
+            learn: true,
+            found: false,
+        }
+    }
+
+    pub(crate) fn with_prefix(prefix: LinePrefix) -> Self {
+        Self {
+            prefix: Some(prefix),
+            learn: false,
+            found: false,
+        }
+    }
+
+    /// Looks for a new document
+    pub(crate) fn reset(&mut self) {
+        if self.learn {
+            self.prefix = None;
+        }
+        self.found = false;
+    }
+
+    /// Returns the line of the document without its prefix, or `None` if the line is not part of
+    /// the document
+    pub(crate) fn strip<'l>(&mut self, line: &'l str) -> Option<&'l str> {
+        if self.found {
+            return self.prefix.as_ref()?.strip(line);
+        }
+
+        let line = match &self.prefix {
+            Some(prefix) => prefix
+                .strip(line)
+                .filter(|line| find_version(line) == Some(0))?,
+            None => {
+                let start = find_version(line)?;
+                self.prefix = Some(LinePrefix::like(&line[..start]));
+                &line[start..]
+            }
+        };
+        self.found = true;
+        Some(line)
+    }
+}
+
+/// Strips the number starting the text, if any
+fn strip_number(text: &str) -> &str {
+    let text = text.trim_start_matches(|c: char| c.is_ascii_digit());
+    match text.strip_prefix('.') {
+        Some(decimals) if decimals.starts_with(|c: char| c.is_ascii_digit()) => {
+            decimals.trim_start_matches(|c: char| c.is_ascii_digit())
+        }
+        _ => text,
+    }
+}
+
+fn find_version(line: &str) -> Option<usize> {
+    line.find("KTAP version")
+        .or_else(|| line.find("TAP version"))
+}
@@ -0,0 +1,397 @@
+use std::{
+    fmt::Display,
+    io::{BufReader, Read},
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{validate, Finding, LocatedError, ReadError, TapReader, TapStatementOwned, TapSummary};
+
+/// Time given to read the output already written by a program that timed out
+const GRACE: Duration = Duration::from_millis(100);
+
+///
+/// Runs test programs, and parses the TAP document they write on their standard output.
+///
+/// ```rust,no_run
+/// use std::{process::Command, time::Duration};
+/// use tap_parser::Harness;
+///
+/// let harness = Harness::new()
+///     .with_timeout(Duration::from_secs(60))
+///     .with_jobs(4);
+/// let report = harness.run_all([
+///     ("t/basic", Command::new("t/basic")),
+///     ("t/subtests", Command::new("t/subtests")),
+/// ]);
+/// print!("{report}");
+/// assert!(report.is_success());
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct Harness {
+    timeout: Option<Duration>,
+    jobs: usize,
+}
+
+///
+/// Problem that makes a test program fail, even if all its tests passed.
+///
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum RunProblem {
+    #[error("Could not run the test program: {0}")]
+    Spawn(String),
+    #[error("Could not read the output of the test program: {0}")]
+    Read(String),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Test program exited with {0}")]
+    Exit(ExitStatus),
+    #[error(transparent)]
+    Parse(#[from] LocatedError),
+    #[error(transparent)]
+    Invalid(#[from] Finding),
+}
+
+///
+/// Result of running a single test program.
+///
+#[derive(Debug)]
+pub struct TestRun {
+    pub name: String,
+    pub statements: Vec<TapStatementOwned>,
+    pub problems: Vec<RunProblem>,
+    pub duration: Duration,
+    /// Standard error of the program, only captured when several programs run in parallel
+    pub stderr: String,
+}
+
+impl TestRun {
+    pub fn summary(&self) -> TapSummary<'_> {
+        TapSummary::new(&self.statements)
+    }
+
+    ///
+    /// Returns true if the program exited successfully, and its document is valid and passed.
+    ///
+    pub fn is_success(&self) -> bool {
+        self.problems.is_empty() && self.summary().is_success()
+    }
+}
+
+///
+/// Results of all the test programs run by a [Harness].
+///
+/// Its [Display] implementation prints a report similar to the one of `prove`.
+///
+#[derive(Debug, Default)]
+pub struct HarnessReport {
+    /// Runs of the programs, in the order they were given
+    pub runs: Vec<TestRun>,
+    /// Time elapsed while running all the programs
+    pub duration: Duration,
+}
+
+impl HarnessReport {
+    pub fn is_success(&self) -> bool {
+        self.runs.iter().all(TestRun::is_success)
+    }
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Self {
+            timeout: None,
+            jobs: 1,
+        }
+    }
+
+    ///
+    /// Kills the test programs that are still running after `timeout`.
+    ///
+    /// Processes started by a program are not killed, but their output is not waited for: a
+    /// program whose output is still open after the timeout also timed out.
+    ///
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    ///
+    /// Runs up to `jobs` test programs at the same time in [run_all](Self::run_all).
+    ///
+    /// When more than one job is allowed, the standard error of the programs is captured in
+    /// [TestRun::stderr] instead of being inherited, so that the outputs of the programs are not
+    /// interleaved.
+    ///
+    pub fn with_jobs(self, jobs: usize) -> Self {
+        Self {
+            jobs: jobs.max(1),
+            ..self
+        }
+    }
+
+    ///
+    /// Runs a test program, and parses its standard output while it runs.
+    ///
+    /// The standard input of the program is closed, and its standard error is inherited unless
+    /// [several jobs](Self::with_jobs) are allowed.
+    ///
+    pub fn run(&self, name: impl Into<String>, mut command: Command) -> TestRun {
+        let start = Instant::now();
+        let mut run = TestRun {
+            name: name.into(),
+            statements: Vec::new(),
+            problems: Vec::new(),
+            duration: Duration::ZERO,
+            stderr: String::new(),
+        };
+
+        let capture_stderr = self.jobs > 1;
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(if capture_stderr {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                run.problems.push(RunProblem::Spawn(e.to_string()));
+                return run;
+            }
+        };
+
+        // The outputs are read by threads that are not waited for after the timeout, as they may
+        // be kept open by processes the program started
+        let stdout = child.stdout.take().expect("The output is piped");
+        let (sender, output) = mpsc::channel();
+        std::thread::spawn(move || read_output(BufReader::new(stdout), sender));
+        let stderr = child.stderr.take().map(|mut stderr| {
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                let mut output = Vec::new();
+                let res = stderr.read_to_end(&mut output);
+                let _ = sender.send((String::from_utf8_lossy(&output).into_owned(), res));
+            });
+            receiver
+        });
+
+        let mut timed_out = false;
+        let status = match self.timeout {
+            None => child.wait(),
+            Some(timeout) => loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => break Ok(status),
+                    Ok(None) if start.elapsed() < timeout => {
+                        std::thread::sleep(Duration::from_millis(10))
+                    }
+                    Ok(None) => {
+                        timed_out = true;
+                        // The program may have exited in the meantime
+                        let _ = child.kill();
+                        break child.wait();
+                    }
+                    Err(e) => break Err(e),
+                }
+            },
+        };
+
+        let limit = self.timeout.map(|timeout| start + timeout + GRACE);
+        let mut output_closed = true;
+        loop {
+            match receive(&output, limit) {
+                Ok(Ok(statement)) => run.statements.push(statement),
+                Ok(Err(problem)) => run.problems.push(problem),
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {
+                    output_closed = false;
+                    break;
+                }
+            }
+        }
+        if let Some(stderr) = stderr {
+            match receive(&stderr, limit) {
+                Ok((stderr, res)) => {
+                    run.stderr = stderr;
+                    if let Err(e) = res {
+                        run.problems.push(RunProblem::Read(e.to_string()));
+                    }
+                }
+                Err(_) => output_closed = false,
+            }
+        }
+
+        if timed_out || !output_closed {
+            let timeout = self.timeout.expect("Only runs with a timeout time out");
+            run.problems.insert(0, RunProblem::Timeout(timeout));
+            timed_out = true;
+        }
+        run.duration = start.elapsed();
+
+        match status {
+            Ok(status) if status.success() => (),
+            // Killing the program is already reported as a timeout
+            Ok(_) if timed_out => (),
+            Ok(status) => run.problems.push(RunProblem::Exit(status)),
+            Err(e) => run.problems.push(RunProblem::Spawn(e.to_string())),
+        }
+
+        run.problems
+            .extend(validate(&run.statements).into_iter().map(RunProblem::from));
+
+        run
+    }
+
+    ///
+    /// Runs test programs, as many at the same time as [allowed](Self::with_jobs). See
+    /// [run](Self::run).
+    ///
+    pub fn run_all<N, I>(&self, commands: I) -> HarnessReport
+    where
+        N: Into<String>,
+        I: IntoIterator<Item = (N, Command)>,
+    {
+        let start = Instant::now();
+        let commands: Vec<(String, Command)> = commands
+            .into_iter()
+            .map(|(name, command)| (name.into(), command))
+            .collect();
+        let mut runs: Vec<Option<TestRun>> = commands.iter().map(|_| None).collect();
+
+        let jobs = self.jobs.min(commands.len());
+        let queue = Mutex::new(commands.into_iter().enumerate());
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let next = queue.lock().expect("A worker panicked").next();
+                            let Some((idx, (name, command))) = next else {
+                                break done;
+                            };
+                            done.push((idx, self.run(name, command)));
+                        }
+                    })
+                })
+                .collect();
+
+            for worker in workers {
+                for (idx, run) in worker.join().expect("A worker panicked") {
+                    runs[idx] = Some(run);
+                }
+            }
+        });
+
+        HarnessReport {
+            runs: runs
+                .into_iter()
+                .map(|run| run.expect("All the programs were run"))
+                .collect(),
+            duration: start.elapsed(),
+        }
+    }
+}
+
+/// Receives a message, waiting until `limit` if there is one
+fn receive<T>(receiver: &Receiver<T>, limit: Option<Instant>) -> Result<T, RecvTimeoutError> {
+    match limit {
+        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        Some(limit) => receiver.recv_timeout(limit.saturating_duration_since(Instant::now())),
+    }
+}
+
+/// Parses the output of a test program, reading it until the program closes it, and sends the
+/// statements and problems as soon as they are read
+fn read_output<R: std::io::BufRead>(
+    mut output: R,
+    sender: mpsc::Sender<Result<TapStatementOwned, RunProblem>>,
+) {
+    let mut reader = TapReader::recovering(&mut output);
+    for statement in &mut reader {
+        let statement = statement.map_err(|e| match e {
+            ReadError::Parse(e) => RunProblem::Parse(e),
+            ReadError::Io(e) => RunProblem::Read(e.to_string()),
+        });
+        if sender.send(statement).is_err() {
+            return;
+        }
+    }
+    for diagnostic in reader.diagnostics() {
+        let _ = sender.send(Err(RunProblem::from(diagnostic)));
+    }
+
+    // Lines after the end of the document must still be consumed, so that the program is not
+    // blocked writing them
+    if let Err(e) = std::io::copy(&mut output, &mut std::io::sink()) {
+        let _ = sender.send(Err(RunProblem::Read(e.to_string())));
+    }
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for HarnessReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.runs.iter().map(|r| r.name.len()).max().unwrap_or(0) + 3;
+        let mut tests = 0;
+        let mut failed_runs = 0;
+
+        for run in &self.runs {
+            let summary = run.summary();
+            tests += summary.total();
+
+            let dots = ".".repeat(width - run.name.len());
+            let result = if run.is_success() { "ok" } else { "FAILED" };
+            writeln!(
+                f,
+                "{} {dots} {result} ({} tests, {:.2}s)",
+                run.name,
+                summary.total(),
+                run.duration.as_secs_f64()
+            )?;
+            if run.is_success() {
+                continue;
+            }
+
+            failed_runs += 1;
+            if summary.bailed {
+                writeln!(f, "    Bailed out")?;
+            }
+            if summary.failed != 0 {
+                writeln!(f, "    Failed {}/{} tests", summary.failed, summary.total())?;
+            }
+            for problem in &run.problems {
+                writeln!(f, "    {problem}")?;
+            }
+            for line in run.stderr.lines() {
+                writeln!(f, "    | {line}")?;
+            }
+        }
+
+        writeln!(
+            f,
+            "Files={}, Tests={tests}, Failed files={failed_runs}, {:.2}s",
+            self.runs.len(),
+            self.duration.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Result: {}",
+            if self.is_success() { "PASS" } else { "FAIL" }
+        )
+    }
+}
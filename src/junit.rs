@@ -0,0 +1,241 @@
+use std::fmt::Write;
+
+use crate::{DirectiveKind, TapDirective, TapStatement, TapTest};
+
+///
+/// Writes statements as a JUnit XML report, with a single `<testsuite>` named `name`.
+///
+/// Each subtest is a nested `<testsuite>` named after the subtest, and each test point a
+/// `<testcase>`. Failures contain the YAML block of the test, and a `Bail out!` is a test case
+/// with an `<error>`. Comments and unknown lines are written in the `<system-out>` of their suite.
+///
+/// A failed subtest ending is a test case of the suite of the subtest, as the subtest may fail
+/// without any failed test. The tests of a subtest whose ending has a `SKIP` or `TODO` directive
+/// are skipped instead of failed.
+///
+pub fn write_junit<W: std::io::Write>(
+    writer: &mut W,
+    name: &str,
+    statements: &[TapStatement<'_>],
+) -> std::io::Result<()> {
+    writer.write_all(junit(name, statements).as_bytes())
+}
+
+///
+/// Converts statements to a JUnit XML report. See [write_junit].
+///
+pub fn junit(name: &str, statements: &[TapStatement<'_>]) -> String {
+    let mut suite = String::new();
+    let counts = write_suite(&mut suite, name, statements, None, 1, None)
+        .expect("Writing to a String can't fail");
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites name=\"{}\" {counts}>\n\
+         {suite}\
+         </testsuites>\n",
+        escape(name)
+    )
+}
+
+/// Number of test cases of a suite, including the nested suites
+#[derive(Default)]
+struct Counts {
+    tests: usize,
+    failures: usize,
+    /// Bail outs, that interrupted the run
+    errors: usize,
+    skipped: usize,
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            r#"tests="{}" failures="{}" errors="{}" skipped="{}""#,
+            self.tests, self.failures, self.errors, self.skipped
+        )
+    }
+}
+
+/// Writes a suite, along with the ending of the subtest it comes from and its position in the
+/// enclosing suite. Failures are expected inside subtests whose ending has a directive, `excuse`.
+fn write_suite(
+    output: &mut String,
+    name: &str,
+    statements: &[TapStatement<'_>],
+    ending: Option<(&TapTest<'_>, usize)>,
+    depth: usize,
+    excuse: Option<&TapDirective<'_>>,
+) -> Result<Counts, std::fmt::Error> {
+    let indent = "  ".repeat(depth);
+    let mut counts = Counts::default();
+    let mut cases = String::new();
+    let mut system_out = String::new();
+    // Number of test points read, used to name the tests with neither number nor description
+    let mut position = 0;
+
+    for statement in statements {
+        match statement {
+            TapStatement::TestPoint(test) => {
+                position += 1;
+                write_case(
+                    &mut cases,
+                    name,
+                    test,
+                    position,
+                    depth + 1,
+                    excuse,
+                    &mut counts,
+                )?
+            }
+            TapStatement::Subtest(subtest) => {
+                position += 1;
+                let name = subtest.name.as_deref().unwrap_or("subtest");
+                let ending = subtest.ending.as_ref();
+                let excuse = ending.and_then(|e| e.directive.as_ref()).or(excuse);
+                let nested = write_suite(
+                    &mut cases,
+                    name,
+                    &subtest.body.statements,
+                    ending.map(|ending| (ending, position)),
+                    depth + 1,
+                    excuse,
+                )?;
+                counts.tests += nested.tests;
+                counts.failures += nested.failures;
+                counts.errors += nested.errors;
+                counts.skipped += nested.skipped;
+            }
+            TapStatement::BailOut(bail_out) => {
+                let message = bail_out.reason.as_deref().unwrap_or_default();
+                write!(
+                    cases,
+                    "{indent}  <testcase name=\"Bail out!\" classname=\"{}\">\n\
+                     {indent}    <error message=\"{}\"/>\n\
+                     {indent}  </testcase>\n",
+                    escape(name),
+                    escape(message),
+                )?;
+                counts.tests += 1;
+                counts.errors += 1;
+            }
+            TapStatement::Comment(comment) => {
+                system_out += &comment.text;
+                system_out += "\n";
+            }
+            TapStatement::Unknown(unknown) => {
+                system_out += &unknown.text;
+                system_out += "\n";
+            }
+            TapStatement::Plan(_) | TapStatement::Pragma(_) => (),
+        }
+    }
+
+    // A subtest can fail without any failed test, for example when its plan is not followed
+    if let Some((ending, position)) = ending {
+        if !ending.result && ending.directive.is_none() && excuse.is_none() {
+            write_case(
+                &mut cases,
+                name,
+                ending,
+                position,
+                depth + 1,
+                None,
+                &mut counts,
+            )?;
+        }
+    }
+
+    writeln!(
+        output,
+        "{indent}<testsuite name=\"{}\" {counts}>",
+        escape(name)
+    )?;
+    output.push_str(&cases);
+    if !system_out.is_empty() {
+        writeln!(
+            output,
+            "{indent}  <system-out>{}</system-out>",
+            escape(&system_out)
+        )?;
+    }
+    writeln!(output, "{indent}</testsuite>")?;
+
+    Ok(counts)
+}
+
+fn write_case(
+    output: &mut String,
+    suite: &str,
+    test: &TapTest<'_>,
+    position: usize,
+    depth: usize,
+    excuse: Option<&TapDirective<'_>>,
+    counts: &mut Counts,
+) -> std::fmt::Result {
+    let indent = "  ".repeat(depth);
+    let name = match (test.unescaped_desc(), test.number) {
+        (Some(desc), _) => desc.into_owned(),
+        (None, Some(number)) => format!("test {number}"),
+        (None, None) => format!("test {position}"),
+    };
+
+    counts.tests += 1;
+    write!(
+        output,
+        r#"{indent}<testcase name="{}" classname="{}""#,
+        escape(&name),
+        escape(suite)
+    )?;
+
+    match test.directive.as_ref().or(excuse) {
+        // Failing TODO tests are expected to fail, they are not failures
+        Some(directive) if directive.kind == DirectiveKind::Skip || !test.result => {
+            counts.skipped += 1;
+            let reason = directive.unescaped_reason().unwrap_or_default();
+            let message = match directive.kind {
+                DirectiveKind::Skip => reason.into_owned(),
+                DirectiveKind::Todo => format!("TODO {reason}").trim_end().to_string(),
+            };
+            write!(
+                output,
+                ">\n{indent}  <skipped message=\"{}\"/>\n{indent}</testcase>\n",
+                escape(&message)
+            )
+        }
+        None if !test.result => {
+            counts.failures += 1;
+            write!(
+                output,
+                ">\n{indent}  <failure message=\"{}\">",
+                escape(&name)
+            )?;
+            for line in &test.yaml {
+                writeln!(output, "{}", escape(line))?;
+            }
+            write!(output, "</failure>\n{indent}</testcase>\n")
+        }
+        _ => writeln!(output, "/>"),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            // Characters that can't appear in an XML document, such as terminal escapes
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                escaped.push(char::REPLACEMENT_CHARACTER)
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,99 +1,479 @@
 //! This crate is a parser for the [Test Anything Protocol](https://testanything.org).
 //!
-//! It handles all the TAP 14 features, including subtests. The main entrypoint is the [TapParser]
-//! structure.
+//! It handles all the TAP 14 features, including subtests. Documents following TAP 13, or TAP 12
+//! (without a version line) are also supported, as well as the [KTAP](TapVersion::KtapV1)
+//! documents of the Linux kernel tests. The main entrypoint is the [TapParser] structure.
 //!
 //! The parser will ignore trailing lines when it is sure it could not be in the TAP document
-//! anymore.
+//! anymore, they are kept in [TapDocument::trailing].
 //!
 //! # Example
 //!
 //! ```rust
-//! use tap_parser::{TapParser, TapStatement, TapPlan, TapTest};
+//! use tap_parser::{Span, TapParser, TapStatement, TapPlan, TapTest, TapVersion};
 //!
 //! let document = "TAP version 14\n1..1\nok 1 - success\nnot ok 2 - fail";
 //! let mut parser = TapParser::new();
+//! let document = parser.parse(document).unwrap();
+//! assert_eq!(document.version, TapVersion::V14);
 //! assert_eq!(
-//!     parser.parse(document).unwrap(),
+//!     document.statements,
 //!     vec![
 //!         TapStatement::Plan(TapPlan {
 //!             count: 1,
-//!             reason: None
+//!             reason: None,
+//!             span: Span { line: 2, start: 15, end: 19, depth: 0 },
 //!         }),
 //!         TapStatement::TestPoint(TapTest {
 //!             result: true,
 //!             number: Some(1),
-//!             desc: Some("success"),
+//!             desc: Some("success".into()),
 //!             directive: None,
 //!             yaml: Vec::new(),
+//!             span: Span { line: 3, start: 20, end: 34, depth: 0 },
 //!         }),
 //!         TapStatement::TestPoint(TapTest {
 //!             result: false,
 //!             number: Some(2),
-//!             desc: Some("fail"),
+//!             desc: Some("fail".into()),
 //!             directive: None,
 //!             yaml: Vec::new(),
+//!             span: Span { line: 4, start: 35, end: 50, depth: 0 },
 //!         }),
 //!     ]
 //! );
 //!
 //! ```
+//!
+//! Documents can also be parsed while they are being produced, either by feeding borrowed lines
+//! to [TapParser::feed_line] or arbitrary chunks of text to a [TapStreamParser]. Documents can be
+//! read from any [BufRead](std::io::BufRead) with a [TapReader].
+//!
+//! The statements of a document and of all its subtests can be walked with a [Visitor], and the
+//! tests they contain can be addressed by their [TestPath].
+//!
+//! Parsing only fails on syntax errors. The consistency of a parsed document, such as the test
+//! count matching the plan, can be checked with [validate]. The results of the tests can be
+//! counted with a [TapSummary].
+//!
+//! Statements can be written back as a TAP document with a [TapWriter], or converted to a JUnit
+//! XML report with [junit].
+//!
+//! Inputs containing several documents back to back are parsed with [parse_documents].
+//!
+//! Documents embedded in log output, with a prefix before each line, can be extracted with
+//! [TapParser::with_extraction].
+//!
+//! Test programs can be run by a [Harness], that parses their output while they run.
+//!
+//! With the `yaml` feature, the YAML blocks of test points can be parsed with
+//! `TapTest::yaml_block`.
+
+use std::{borrow::Cow, num::ParseIntError};
+
+use extract::Extractor;
+
+mod documents;
+mod extract;
+mod harness;
+mod junit;
+mod path;
+mod stream;
+mod summary;
+mod validate;
+mod visit;
+mod writer;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+pub use documents::{parse_documents, ParsedDocument};
+pub use extract::LinePrefix;
+pub use harness::{Harness, HarnessReport, RunProblem, TestRun};
+pub use junit::{junit, write_junit};
+pub use path::{find_test, test_paths, TestPath, TestPaths};
+pub use stream::{TapReader, TapStreamParser};
+pub use summary::TapSummary;
+pub use validate::{validate, Finding, FindingKind};
+pub use visit::{walk, walk_mut, walk_statement, walk_statement_mut, Visitor, VisitorMut};
+pub use writer::{write, TapWriter};
+#[cfg(feature = "yaml")]
+pub use yaml::YamlBlock;
+
+/// A [TapStatement] that does not borrow from the input
+pub type TapStatementOwned = TapStatement<'static>;
 
-use std::num::ParseIntError;
+fn into_owned_text(text: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(text.into_owned())
+}
+
+/// Replaces the `\#` and `\\` escapes by the character they escape
+fn unescape(text: &str) -> Cow<'_, str> {
+    if !text.contains('\\') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ ('#' | '\\'))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+
+    Cow::Owned(unescaped)
+}
+
+///
+/// Location of a statement or an error in the parsed input.
+///
+/// Byte offsets are relative to the start of the whole input, even for statements inside
+/// subtests.
+///
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    /// 1-based number of the first line
+    pub line: usize,
+    /// Byte offset of the start of the first line
+    pub start: usize,
+    /// Byte offset of the end of the last line, excluding the line terminator
+    pub end: usize,
+    /// Number of subtests enclosing the statement
+    pub depth: usize,
+}
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct TapPlan<'a> {
     pub count: usize,
-    pub reason: Option<&'a str>,
+    pub reason: Option<Cow<'a, str>>,
+    pub span: Span,
+}
+
+impl<'a> TapPlan<'a> {
+    ///
+    /// Returns the skip reason with its `\#` and `\\` escapes resolved. The [reason](Self::reason)
+    /// field keeps the text as written in the document.
+    ///
+    pub fn unescaped_reason(&self) -> Option<Cow<'_, str>> {
+        self.reason.as_deref().map(unescape)
+    }
+
+    pub fn into_owned(self) -> TapPlan<'static> {
+        TapPlan {
+            count: self.count,
+            reason: self.reason.map(into_owned_text),
+            span: self.span,
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum DirectiveKind {
     Skip,
     Todo,
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct TapDirective<'a> {
     pub kind: DirectiveKind,
-    pub reason: Option<&'a str>,
+    pub reason: Option<Cow<'a, str>>,
+}
+
+impl<'a> TapDirective<'a> {
+    ///
+    /// Returns the reason with its `\#` and `\\` escapes resolved. The [reason](Self::reason)
+    /// field keeps the text as written in the document.
+    ///
+    pub fn unescaped_reason(&self) -> Option<Cow<'_, str>> {
+        self.reason.as_deref().map(unescape)
+    }
+
+    pub fn into_owned(self) -> TapDirective<'static> {
+        TapDirective {
+            kind: self.kind,
+            reason: self.reason.map(into_owned_text),
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct TapTest<'a> {
     pub result: bool,
     pub number: Option<usize>,
-    pub desc: Option<&'a str>,
+    pub desc: Option<Cow<'a, str>>,
     pub directive: Option<TapDirective<'a>>,
-    pub yaml: Vec<&'a str>,
+    pub yaml: Vec<Cow<'a, str>>,
+    /// Span of the test line, and of its YAML block if present
+    pub span: Span,
+}
+
+impl<'a> TapTest<'a> {
+    ///
+    /// Returns the description with its `\#` and `\\` escapes resolved. The [desc](Self::desc)
+    /// field keeps the text as written in the document.
+    ///
+    pub fn unescaped_desc(&self) -> Option<Cow<'_, str>> {
+        self.desc.as_deref().map(unescape)
+    }
+
+    pub fn into_owned(self) -> TapTest<'static> {
+        TapTest {
+            result: self.result,
+            number: self.number,
+            desc: self.desc.map(into_owned_text),
+            directive: self.directive.map(TapDirective::into_owned),
+            yaml: self.yaml.into_iter().map(into_owned_text).collect(),
+            span: self.span,
+        }
+    }
 }
 
+///
+/// A whole TAP document, as returned by [TapParser::parse], or the body of a subtest.
+///
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Debug)]
-pub struct TapSubDocument<'a> {
-    pub name: Option<&'a str>,
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TapDocument<'a> {
+    /// Version declared by the document, subtests have the version of the enclosing document
+    pub version: TapVersion,
     pub statements: Vec<TapStatement<'a>>,
-    pub ending: TapTest<'a>,
+    /// Lines following the end of the document, that were ignored
+    pub trailing: Vec<Cow<'a, str>>,
+}
+
+impl<'a> TapDocument<'a> {
+    pub fn plan(&self) -> Option<&TapPlan<'a>> {
+        self.statements.iter().find_map(|s| match s {
+            TapStatement::Plan(plan) => Some(plan),
+            _ => None,
+        })
+    }
+
+    ///
+    /// Returns true if the plan follows the tests, as written by producers that don't know the
+    /// number of tests in advance.
+    ///
+    pub fn is_plan_last(&self) -> bool {
+        let is_test =
+            |s: &TapStatement| matches!(s, TapStatement::TestPoint(_) | TapStatement::Subtest(_));
+        let plan = self
+            .statements
+            .iter()
+            .position(|s| matches!(s, TapStatement::Plan(_)));
+        match (plan, self.statements.iter().position(is_test)) {
+            (Some(plan), Some(test)) => plan > test,
+            _ => false,
+        }
+    }
+
+    ///
+    /// Iterates over the test points of the document, including the endings of its subtests.
+    ///
+    pub fn tests(&self) -> impl Iterator<Item = &TapTest<'a>> + '_ {
+        self.statements.iter().filter_map(|s| match s {
+            TapStatement::TestPoint(test) => Some(test),
+            TapStatement::Subtest(subtest) => subtest.ending.as_ref(),
+            _ => None,
+        })
+    }
+
+    pub fn subtests(&self) -> impl Iterator<Item = &TapSubDocument<'a>> + '_ {
+        self.statements.iter().filter_map(|s| match s {
+            TapStatement::Subtest(subtest) => Some(subtest),
+            _ => None,
+        })
+    }
+
+    pub fn pragmas(&self) -> impl Iterator<Item = &TapPragma<'a>> + '_ {
+        self.statements.iter().filter_map(|s| match s {
+            TapStatement::Pragma(pragma) => Some(pragma),
+            _ => None,
+        })
+    }
+
+    ///
+    /// Returns true if the run was aborted by a `Bail out!`, in the document or in one of its
+    /// subtests. See [TapParser::is_bailed].
+    ///
+    pub fn is_bailed(&self) -> bool {
+        self.statements.iter().any(|s| match s {
+            TapStatement::BailOut(_) => true,
+            TapStatement::Subtest(subtest) => subtest.body.is_bailed(),
+            _ => false,
+        })
+    }
+
+    pub fn into_owned(self) -> TapDocument<'static> {
+        TapDocument {
+            version: self.version,
+            statements: self
+                .statements
+                .into_iter()
+                .map(TapStatement::into_owned)
+                .collect(),
+            trailing: self.trailing.into_iter().map(into_owned_text).collect(),
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TapSubDocument<'a> {
+    pub name: Option<Cow<'a, str>>,
+    pub body: TapDocument<'a>,
+    /// Test point closing the subtest, missing if the run bailed out during the subtest
+    pub ending: Option<TapTest<'a>>,
+    /// Span of the whole subtest, from its first line to its ending
+    pub span: Span,
+}
+
+impl<'a> TapSubDocument<'a> {
+    pub fn into_owned(self) -> TapSubDocument<'static> {
+        TapSubDocument {
+            name: self.name.map(into_owned_text),
+            body: self.body.into_owned(),
+            ending: self.ending.map(TapTest::into_owned),
+            span: self.span,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TapComment<'a> {
+    pub text: Cow<'a, str>,
+    pub span: Span,
+}
+
+impl<'a> TapComment<'a> {
+    pub fn into_owned(self) -> TapComment<'static> {
+        TapComment {
+            text: into_owned_text(self.text),
+            span: self.span,
+        }
+    }
+}
+
+///
+/// A `pragma +key` or `pragma -key` line, enabling or disabling a parser option.
+///
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TapPragma<'a> {
+    pub key: Cow<'a, str>,
+    pub enabled: bool,
+    pub span: Span,
+}
+
+impl<'a> TapPragma<'a> {
+    pub fn into_owned(self) -> TapPragma<'static> {
+        TapPragma {
+            key: into_owned_text(self.key),
+            enabled: self.enabled,
+            span: self.span,
+        }
+    }
+}
+
+///
+/// A `Bail out!` line, aborting the run. No statement follows it.
+///
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TapBailOut<'a> {
+    pub reason: Option<Cow<'a, str>>,
+    pub span: Span,
+}
+
+impl<'a> TapBailOut<'a> {
+    pub fn into_owned(self) -> TapBailOut<'static> {
+        TapBailOut {
+            reason: self.reason.map(into_owned_text),
+            span: self.span,
+        }
+    }
+}
+
+///
+/// A line that is not part of the TAP syntax. These lines are only kept when the `strict` pragma
+/// is disabled, otherwise they are errors.
+///
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TapUnknown<'a> {
+    pub text: Cow<'a, str>,
+    pub span: Span,
+}
+
+impl<'a> TapUnknown<'a> {
+    pub fn into_owned(self) -> TapUnknown<'static> {
+        TapUnknown {
+            text: into_owned_text(self.text),
+            span: self.span,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum TapStatement<'a> {
     Plan(TapPlan<'a>),
     TestPoint(TapTest<'a>),
-    Comment(&'a str),
+    Comment(TapComment<'a>),
     Subtest(TapSubDocument<'a>),
+    Pragma(TapPragma<'a>),
+    Unknown(TapUnknown<'a>),
+    BailOut(TapBailOut<'a>),
 }
 
 impl<'a> TapStatement<'a> {
-    fn as_test_mut(&mut self) -> &mut TapTest<'a> {
+    ///
+    /// Converts the statement to a statement that does not borrow from the input anymore.
+    ///
+    pub fn into_owned(self) -> TapStatementOwned {
         match self {
-            Self::TestPoint(t) => t,
-            Self::Subtest(t) => &mut t.ending,
+            Self::Plan(p) => TapStatement::Plan(p.into_owned()),
+            Self::TestPoint(t) => TapStatement::TestPoint(t.into_owned()),
+            Self::Comment(c) => TapStatement::Comment(c.into_owned()),
+            Self::Subtest(s) => TapStatement::Subtest(s.into_owned()),
+            Self::Pragma(p) => TapStatement::Pragma(p.into_owned()),
+            Self::Unknown(u) => TapStatement::Unknown(u.into_owned()),
+            Self::BailOut(b) => TapStatement::BailOut(b.into_owned()),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Plan(p) => p.span,
+            Self::TestPoint(t) => t.span,
+            Self::Comment(c) => c.span,
+            Self::Subtest(s) => s.span,
+            Self::Pragma(p) => p.span,
+            Self::Unknown(u) => u.span,
+            Self::BailOut(b) => b.span,
+        }
+    }
+
+    fn set_yaml(&mut self, yaml: Vec<Cow<'a, str>>, end: usize) {
+        match self {
+            Self::TestPoint(t) => {
+                t.yaml = yaml;
+                t.span.end = end;
+            }
+            Self::Subtest(t) => {
+                let ending = t.ending.as_mut().unwrap();
+                ending.yaml = yaml;
+                ending.span.end = end;
+                t.span.end = end;
+            }
             _ => unreachable!("Statement {self:?} was not a TestPoint/Subtest"),
         }
     }
@@ -103,14 +483,61 @@ enum State {
     Body,
     AfterTest,
     Yaml,
+    /// YAML block that does not follow a test point, only produced when recovering from errors
+    OrphanYaml,
     Subtest,
 }
 
+/// Converts a slice of the line being parsed into text stored in the statements
+type Text<'l, 'a> = fn(&'l str) -> Cow<'a, str>;
+
+///
+/// Version of the TAP specification followed by a document.
+///
+/// Documents without a version line are TAP 12 documents, they can't contain subtests nor YAML
+/// blocks.
+///
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum TapVersion {
+    V12,
+    V13,
+    V14,
+    ///
+    /// KTAP, the dialect of TAP 14 produced by the Linux kernel tests, starting with
+    /// `KTAP version 1`.
+    ///
+    /// Its subtests start with their own version line, optionally followed by an indented
+    /// `# Subtest` header, and lines starting with `#` may appear between the statements of a
+    /// subtest and its ending. Directives other than `SKIP` and `TODO`, such as `XFAIL` or
+    /// `TIMEOUT`, are not errors: they are kept in the description, and the test keeps its result.
+    ///
+    KtapV1,
+}
+
+/// Position of the line being parsed in the whole input
+#[derive(Clone, Copy)]
+struct Location {
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
 pub struct TapParser<'a> {
+    depth: usize,
+    recover: bool,
+    strict: bool,
+    extractor: Option<Extractor>,
+    diagnostics: Vec<LocatedError>,
+    lines_read: usize,
+    offset: usize,
+    version: Option<TapVersion>,
     in_body: bool,
     done: bool,
+    bailed: bool,
     state: State,
-    yaml_accumulator: Vec<&'a str>,
+    yaml_accumulator: Vec<Cow<'a, str>>,
+    yaml_end: usize,
     statements: Vec<TapStatement<'a>>,
     read_plan: bool,
     sub_parser: Option<SubTapParser<'a>>,
@@ -120,7 +547,24 @@ pub struct TapParser<'a> {
 
 struct SubTapParser<'a> {
     parser: Box<TapParser<'a>>,
-    name: Option<&'a str>,
+    name: Option<Cow<'a, str>>,
+    span: Span,
+}
+
+impl<'a> SubTapParser<'a> {
+    /// Returns the parsed subtest, whose last line ends at `end`
+    fn into_subtest(self, ending: Option<TapTest<'a>>, end: usize) -> TapSubDocument<'a> {
+        TapSubDocument {
+            name: self.name,
+            body: TapDocument {
+                version: self.parser.version.unwrap_or(TapVersion::V12),
+                statements: self.parser.statements,
+                trailing: Vec::new(),
+            },
+            ending,
+            span: Span { end, ..self.span },
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
@@ -141,12 +585,37 @@ pub enum Error {
     InvalidYaml,
     #[error("A closing yaml line must be preceded by an opening line")]
     InvalidYamlClose,
-    #[error("Bailed: `{0}`")]
-    Bailed(String),
     #[error("Line is unknown: {0}")]
     UnknownLine(String),
     #[error("Duplicated plan")]
     DuplicatedPlan,
+    #[error("YAML block is invalid: {0}")]
+    InvalidYamlBlock(String),
+}
+
+impl Error {
+    pub fn at(self, span: Span) -> LocatedError {
+        LocatedError { error: self, span }
+    }
+}
+
+///
+/// An [Error] along with the location of the line that caused it.
+///
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("Line {}: {error}", .span.line)]
+pub struct LocatedError {
+    #[source]
+    pub error: Error,
+    pub span: Span,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadError {
+    #[error("Could not read the document")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] LocatedError),
 }
 
 ///
@@ -156,9 +625,19 @@ pub enum Error {
 impl<'a> TapParser<'a> {
     pub fn new() -> Self {
         Self {
+            depth: 0,
+            recover: false,
+            strict: false,
+            extractor: None,
+            diagnostics: Vec::new(),
+            lines_read: 0,
+            offset: 0,
+            version: None,
             in_body: false,
             done: false,
+            bailed: false,
             yaml_accumulator: Vec::new(),
+            yaml_end: 0,
             statements: Vec::new(),
             read_plan: false,
             state: State::Body,
@@ -168,15 +647,188 @@ impl<'a> TapParser<'a> {
         }
     }
 
-    fn read_test_line(&mut self, result: bool, test: &'a str) -> Result<TapTest<'a>, Error> {
-        let (number, end): (Option<usize>, _) = match test.split_once(' ') {
-            Some((n, end)) if n.chars().all(|c| c.is_ascii_digit()) => (Some(n.parse()?), end),
+    ///
+    /// Creates a parser that recovers from errors instead of stopping at the first one.
+    ///
+    /// Invalid lines are skipped or interpreted as best as possible, and the errors are recorded
+    /// as diagnostics, that can be retrieved with [diagnostics](Self::diagnostics).
+    ///
+    pub fn recovering() -> Self {
+        Self {
+            recover: true,
+            ..Self::new()
+        }
+    }
+
+    ///
+    /// Looks for the document inside arbitrary output, such as the log of a CI job or of a
+    /// kernel console, where each line may be decorated with a prefix.
+    ///
+    /// Lines are ignored until one contains a `TAP version` or `KTAP version` line, the text
+    /// before it is the prefix of the document. Following lines are stripped of a prefix
+    /// [shaped like](LinePrefix::like) this one, and ignored if they don't have one. Documents
+    /// without a version line can't be extracted.
+    ///
+    /// The [spans](Span) of the statements are positions in the whole output.
+    ///
+    /// ```rust
+    /// use tap_parser::TapParser;
+    ///
+    /// let log = "\
+    /// [    0.000000] Booting the kernel
+    /// [    1.000000] KTAP version 1
+    /// [    1.000010] 1..1
+    /// [    1.000020] ok 1 example
+    /// ";
+    /// let document = TapParser::new().with_extraction().parse(log).unwrap();
+    /// assert_eq!(document.statements.len(), 2);
+    /// assert_eq!(document.statements[1].span().line, 4);
+    /// ```
+    ///
+    pub fn with_extraction(self) -> Self {
+        Self {
+            extractor: Some(Extractor::new()),
+            ..self
+        }
+    }
+
+    ///
+    /// Looks for the document inside arbitrary output, where each line of the document starts
+    /// with `prefix`. See [with_extraction](Self::with_extraction).
+    ///
+    pub fn with_prefix(self, prefix: LinePrefix) -> Self {
+        Self {
+            extractor: Some(Extractor::with_prefix(prefix)),
+            ..self
+        }
+    }
+
+    fn sub_parser(&self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            recover: self.recover,
+            strict: self.strict,
+            version: self.version,
+            ..Self::new()
+        }
+    }
+
+    ///
+    /// Returns the version of the document, once its first line was read.
+    ///
+    pub fn version(&self) -> Option<TapVersion> {
+        self.version
+    }
+
+    ///
+    /// Returns true if the `strict` pragma is enabled, in which case lines that are not part of
+    /// the TAP syntax are errors.
+    ///
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    ///
+    /// Returns true if the run was aborted by a `Bail out!`, at any depth. The document then ends
+    /// with a [BailOut](TapStatement::BailOut) statement, inside the subtests that were
+    /// interrupted.
+    ///
+    pub fn is_bailed(&self) -> bool {
+        self.bailed
+    }
+
+    fn span(&self, location: Location) -> Span {
+        Span {
+            line: location.line,
+            start: location.start,
+            end: location.end,
+            depth: self.depth,
+        }
+    }
+
+    /// Returns the error, or records it if the parser recovers from errors
+    fn report(&mut self, error: LocatedError) -> Result<(), LocatedError> {
+        if self.recover {
+            self.diagnostics.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    fn read_directive<'l>(
+        directive: &'l str,
+        text: Text<'l, 'a>,
+    ) -> Result<TapDirective<'a>, Error> {
+        let directive = directive.trim();
+        if directive.len() < 4 {
+            return Err(Error::MalformedDirective(directive.into()));
+        }
+
+        let kind = match directive.as_bytes()[..4].to_ascii_lowercase().as_slice() {
+            b"skip" => DirectiveKind::Skip,
+            b"todo" => DirectiveKind::Todo,
+            _ => return Err(Error::MalformedDirective(directive.into())),
+        };
+
+        let reason = if directive.len() == 4 {
+            None
+        } else {
+            Some(text(directive[4..].trim()))
+        };
+
+        Ok(TapDirective { kind, reason })
+    }
+
+    /// Parses a `pragma` line, returns `None` if the line is not a valid pragma.
+    fn read_pragma<'l>(line: &'l str, span: Span, text: Text<'l, 'a>) -> Option<TapPragma<'a>> {
+        let pragma = line.strip_prefix("pragma ")?.trim();
+        let (enabled, key) = if let Some(key) = pragma.strip_prefix('+') {
+            (true, key)
+        } else if let Some(key) = pragma.strip_prefix('-') {
+            (false, key)
+        } else {
+            return None;
+        };
+
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return None;
+        }
+
+        Some(TapPragma {
+            key: text(key),
+            enabled,
+            span,
+        })
+    }
+
+    /// Parses a test line. Invalid parts are left out of the returned test, and reported in
+    /// the returned error.
+    fn read_test_line<'l>(
+        &self,
+        result: bool,
+        test: &'l str,
+        span: Span,
+        text: Text<'l, 'a>,
+    ) -> (TapTest<'a>, Option<Error>) {
+        let mut error = None;
+
+        let (number, end) = match test.split_once(' ') {
+            Some((n, end)) if n.chars().all(|c| c.is_ascii_digit()) => (Some(n), end),
             None if !test.is_empty() && test.chars().all(|c| c.is_ascii_digit()) => {
-                (Some(test.parse()?), "")
+                (Some(test), "")
             }
             _ => (None, test),
         };
 
+        let number = match number.map(str::parse::<usize>).transpose() {
+            Ok(number) => number,
+            Err(e) => {
+                error = Some(e.into());
+                None
+            }
+        };
+
         let end = end.strip_prefix('-').unwrap_or(end).trim();
 
         let mut escaped = false;
@@ -186,65 +838,134 @@ impl<'a> TapParser<'a> {
                 false
             }
             b'#' if !escaped => true,
-            b'#' if escaped => {
+            _ => {
                 escaped = false;
                 false
             }
-            _ => false,
         });
 
         let mut desc = end;
         let mut directive = None;
         if let Some((idx, _)) = directive_start {
-            if idx == end.len() - 1 {
-                return Err(Error::MalformedDirective("".into()));
+            match Self::read_directive(&end[idx + 1..], text) {
+                Ok(d) => {
+                    desc = end[..idx].trim();
+                    directive = Some(d);
+                }
+                // Unsupported KTAP directives only add details to the description
+                Err(_) if self.version == Some(TapVersion::KtapV1) => (),
+                Err(e) => {
+                    desc = end[..idx].trim();
+                    error = error.or(Some(e));
+                }
             }
+        }
 
-            desc = end[..idx].trim();
-            let directive_str = end[idx + 1..].trim();
-            if directive_str.len() < 4 {
-                return Err(Error::MalformedDirective(directive_str.into()));
-            }
+        let test = TapTest {
+            result,
+            number,
+            desc: if desc.is_empty() {
+                None
+            } else {
+                Some(text(desc))
+            },
+            directive,
+            yaml: Vec::new(),
+            span,
+        };
 
-            let directive_kind = directive_str.as_bytes()[..4].to_ascii_lowercase();
+        (test, error)
+    }
 
-            let kind = match directive_kind.as_slice() {
-                b"skip" => DirectiveKind::Skip,
-                b"todo" => DirectiveKind::Todo,
-                _ => return Err(Error::MalformedDirective(directive_str.into())),
-            };
+    fn read_sub_line<'l>(
+        &mut self,
+        line: &'l str,
+        location: Location,
+        text: Text<'l, 'a>,
+    ) -> Result<(), LocatedError> {
+        let sub_parser = self.sub_parser.as_mut().unwrap();
 
-            let reason = if directive_str.len() == 4 {
-                None
-            } else {
-                Some(directive_str[4..].trim())
+        if let Some(version) = read_version(line) {
+            let span = sub_parser.parser.span(location);
+            return match version {
+                Ok(version) if version >= TapVersion::V13 => Ok(()),
+                Ok(_) => self.report(Error::InvalidVersion("12".into()).at(span)),
+                Err(e) => self.report(e.at(span)),
             };
+        }
 
-            directive = Some(TapDirective { kind, reason });
+        // TAP 13 and KTAP producers announce subtests with an indented header
+        if matches!(self.version, Some(TapVersion::V13 | TapVersion::KtapV1))
+            && sub_parser.name.is_none()
+            && sub_parser.parser.statements.is_empty()
+            && is_subtest_header(line)
+        {
+            sub_parser.name = line.split_once(':').map(|(_, n)| text(n.trim()));
+            return Ok(());
         }
 
-        Ok(TapTest {
-            result,
-            number,
-            desc: if desc.is_empty() { None } else { Some(desc) },
-            directive,
-            yaml: Vec::new(),
-        })
+        let res = sub_parser.parser.read_body_line(line, location, text);
+        self.diagnostics.append(&mut sub_parser.parser.diagnostics);
+        if sub_parser.parser.bailed {
+            self.end_bailed_subtest();
+            self.bailed = true;
+            self.done = true;
+        }
+        res
+    }
+
+    /// Pushes the subtest being parsed, that was interrupted by a `Bail out!`
+    fn end_bailed_subtest(&mut self) {
+        let sub_parser = self.sub_parser.take().unwrap();
+        let end = sub_parser
+            .parser
+            .statements
+            .last()
+            .map_or(sub_parser.span.end, |s| s.span().end);
+
+        self.statements
+            .push(TapStatement::Subtest(sub_parser.into_subtest(None, end)));
+        self.state = State::Body;
+    }
+
+    fn read_bail_out<'l>(&mut self, reason: &'l str, span: Span, text: Text<'l, 'a>) {
+        let reason = reason.trim();
+        self.statements.push(TapStatement::BailOut(TapBailOut {
+            reason: (!reason.is_empty()).then(|| text(reason)),
+            span,
+        }));
+        self.state = State::Body;
+        self.bailed = true;
+        self.done = true;
     }
 
-    fn read_body_line(&mut self, line: &'a str) -> Result<(), Error> {
+    fn read_body_line<'l>(
+        &mut self,
+        line: &'l str,
+        location: Location,
+        text: Text<'l, 'a>,
+    ) -> Result<(), LocatedError> {
+        let span = self.span(location);
+
         if let Some(pr) = line.strip_prefix("1..") {
             if self.read_plan {
-                return Err(Error::DuplicatedPlan);
+                return self.report(Error::DuplicatedPlan.at(span));
             }
 
             let (count, reason) = match pr.split_once('#') {
-                None => (pr.trim().parse()?, None),
-                Some((num, reason)) => (num.trim().parse()?, Some(reason.trim())),
+                None => (pr.trim().parse(), None),
+                Some((num, reason)) => (num.trim().parse(), Some(text(reason.trim()))),
+            };
+            let count = match count {
+                Ok(count) => count,
+                Err(e) => return self.report(Error::from(e).at(span)),
             };
 
-            self.statements
-                .push(TapStatement::Plan(TapPlan { count, reason }));
+            self.statements.push(TapStatement::Plan(TapPlan {
+                count,
+                reason,
+                span,
+            }));
 
             if self.in_body {
                 self.done = true;
@@ -259,18 +980,20 @@ impl<'a> TapParser<'a> {
         }
 
         match self.state {
-            State::AfterTest if line == "  ---" => {
+            State::AfterTest if line == "  ---" && self.version >= Some(TapVersion::V13) => {
                 self.state = State::Yaml;
                 Ok(())
             }
             State::Subtest => {
-                if line.len() >= 9 && line.as_bytes()[0..9].to_ascii_lowercase() == b"bail out!" {
-                    Err(Error::Bailed(line[9..].trim().to_string()))
+                if line.len() >= 9 && line.as_bytes()[0..9].eq_ignore_ascii_case(b"bail out!") {
+                    self.end_bailed_subtest();
+                    self.read_bail_out(&line[9..], span, text);
+                    Ok(())
                 } else if line.starts_with("ok") || line.starts_with("not ok") {
                     let sub_parser = self.sub_parser.take().unwrap();
 
                     if !(sub_parser.parser.done || sub_parser.parser.read_plan) {
-                        return Err(Error::UnexpectedEOD);
+                        self.report(Error::UnexpectedEOD.at(span))?;
                     }
 
                     let (result, test) = if let Some(test) = line.strip_prefix("ok") {
@@ -281,109 +1004,365 @@ impl<'a> TapParser<'a> {
                         unreachable!()
                     };
 
-                    let sub_doc = TapSubDocument {
-                        statements: sub_parser.parser.statements,
-                        name: sub_parser.name,
-                        ending: self.read_test_line(result, test)?,
-                    };
+                    let (ending, error) = self.read_test_line(result, test, span, text);
+                    if let Some(e) = error {
+                        self.report(e.at(span))?;
+                    }
 
+                    let sub_doc = sub_parser.into_subtest(Some(ending), span.end);
                     self.statements.push(TapStatement::Subtest(sub_doc));
                     self.state = State::AfterTest;
                     self.test_seen += 1;
 
                     Ok(())
+                } else if let Some(comment) = line.strip_prefix('#') {
+                    // Comments of the enclosing document, such as the results of the subtest
+                    // written by KTAP producers before its ending, are kept along its statements
+                    let sub_parser = &mut self.sub_parser.as_mut().unwrap().parser;
+                    let span = sub_parser.span(location);
+                    sub_parser
+                        .statements
+                        .push(TapStatement::Comment(TapComment {
+                            text: text(comment.trim()),
+                            span,
+                        }));
+                    Ok(())
+                } else if line.trim().is_empty() {
+                    // Blank lines are ignored, like in the body of the document
+                    Ok(())
+                } else if !self.strict && !line.starts_with(char::is_whitespace) {
+                    // Stray output of the subtest, kept along its statements
+                    let sub_parser = &mut self.sub_parser.as_mut().unwrap().parser;
+                    let span = sub_parser.span(location);
+                    sub_parser
+                        .statements
+                        .push(TapStatement::Unknown(TapUnknown {
+                            text: text(line),
+                            span,
+                        }));
+                    Ok(())
                 } else if line.len() < 4 || &line.as_bytes()[0..4] != b"    " {
-                    Err(Error::Misindent {
-                        expected: 4,
-                        line: line.to_string(),
-                    })
-                } else if let Some(v) = line.strip_prefix("    TAP version") {
-                    if v.trim() == "14" {
-                        Ok(())
-                    } else {
-                        Err(Error::InvalidVersion(v.trim().into()))
-                    }
+                    self.report(
+                        Error::Misindent {
+                            expected: 4,
+                            line: line.to_string(),
+                        }
+                        .at(span),
+                    )
                 } else {
-                    self.sub_parser
-                        .as_mut()
-                        .unwrap()
-                        .parser
-                        .read_body_line(&line[4..])
+                    self.read_sub_line(&line[4..], location, text)
                 }
             }
             State::Body | State::AfterTest => {
-                if !self.read_plan {
-                    self.in_body = true;
+                // Pragmas may precede the plan without starting the body
+                if let Some(pragma) = Self::read_pragma(line, span, text) {
+                    if pragma.key == "strict" {
+                        self.strict = pragma.enabled;
+                    }
+                    self.statements.push(TapStatement::Pragma(pragma));
+                    self.state = State::Body;
+                    return Ok(());
                 }
 
-                if line.starts_with("    ")
-                    || (line.len() >= 9
-                        && line.as_bytes()[0..9].to_ascii_lowercase() == b"# subtest")
+                // Only tests start the body: comments and stray output may precede a leading plan
+                if self.version >= Some(TapVersion::V13)
+                    && (line.starts_with("    ") || is_subtest_header(line))
                 {
+                    self.in_body = true;
                     self.state = State::Subtest;
                     let name = if line.starts_with('#') {
-                        line.split_once(':').map(|(_, n)| n.trim())
+                        line.split_once(':').map(|(_, n)| text(n.trim()))
                     } else {
                         None
                     };
-                    let mut sub_parser = SubTapParser {
-                        parser: Box::new(TapParser::new()),
+                    self.sub_parser = Some(SubTapParser {
+                        parser: Box::new(self.sub_parser()),
                         name,
-                    };
+                        span,
+                    });
                     if let Some(line) = line.strip_prefix("    ") {
-                        sub_parser.parser.read_body_line(line)?;
+                        self.read_sub_line(line, location, text)?;
                     }
-                    self.sub_parser = Some(sub_parser);
                     Ok(())
                 } else if let Some(test_point) = line.strip_prefix("ok") {
-                    let test = self.read_test_line(true, test_point.trim())?;
-                    self.state = State::AfterTest;
-                    self.statements.push(TapStatement::TestPoint(test));
-                    self.test_seen += 1;
-                    Ok(())
+                    self.read_test_point(true, test_point.trim(), span, text)
                 } else if let Some(test_point) = line.strip_prefix("not ok") {
-                    let test = self.read_test_line(false, test_point.trim())?;
-                    self.state = State::AfterTest;
-                    self.statements.push(TapStatement::TestPoint(test));
-                    self.test_seen += 1;
+                    self.read_test_point(false, test_point.trim(), span, text)
+                } else if line == "  ---" && self.version >= Some(TapVersion::V13) {
+                    self.report(Error::InvalidYaml.at(span))?;
+                    self.state = State::OrphanYaml;
                     Ok(())
-                } else if line == "  ---" {
-                    Err(Error::InvalidYaml)
-                } else if line == "  ..." {
-                    Err(Error::InvalidYamlClose)
+                } else if line == "  ..." && self.version >= Some(TapVersion::V13) {
+                    self.report(Error::InvalidYamlClose.at(span))
                 } else if line.len() >= 9
-                    && line.as_bytes()[0..9].to_ascii_lowercase() == b"bail out!"
+                    && line.as_bytes()[0..9].eq_ignore_ascii_case(b"bail out!")
                 {
-                    Err(Error::Bailed(line[9..].trim().to_string()))
+                    self.read_bail_out(&line[9..], span, text);
+                    Ok(())
                 } else if let Some(comment) = line.strip_prefix('#') {
-                    self.statements.push(TapStatement::Comment(comment.trim()));
+                    self.statements.push(TapStatement::Comment(TapComment {
+                        text: text(comment.trim()),
+                        span,
+                    }));
                     self.state = State::Body;
                     Ok(())
-                } else if line.trim().is_empty() || line.starts_with("pragma ") {
+                } else if line.trim().is_empty() {
                     Ok(())
+                } else if self.strict {
+                    self.report(Error::UnknownLine(line.into()).at(span))
                 } else {
-                    Err(Error::UnknownLine(line.into()))
+                    self.statements.push(TapStatement::Unknown(TapUnknown {
+                        text: text(line),
+                        span,
+                    }));
+                    self.state = State::Body;
+                    Ok(())
                 }
             }
-            State::Yaml => {
+            State::Yaml | State::OrphanYaml => {
                 if line == "  ..." {
-                    self.statements.last_mut().unwrap().as_test_mut().yaml =
-                        std::mem::take(&mut self.yaml_accumulator);
-                    self.state = State::Body;
+                    self.yaml_end = span.end;
+                    self.end_yaml();
                     Ok(())
                 } else if line.len() < 2 || &line.as_bytes()[..2] != b"  " {
-                    Err(Error::Misindent {
-                        expected: 2,
-                        line: line.to_string(),
-                    })
+                    self.report(
+                        Error::Misindent {
+                            expected: 2,
+                            line: line.to_string(),
+                        }
+                        .at(span),
+                    )?;
+
+                    // The YAML block is considered to end on the previous line
+                    self.end_yaml();
+                    self.read_body_line(line, location, text)
                 } else {
-                    self.yaml_accumulator.push(&line[2..]);
+                    if let State::Yaml = self.state {
+                        self.yaml_accumulator.push(text(&line[2..]));
+                    }
+                    self.yaml_end = span.end;
                     Ok(())
                 }
             }
         }
     }
 
+    fn read_test_point<'l>(
+        &mut self,
+        result: bool,
+        test: &'l str,
+        span: Span,
+        text: Text<'l, 'a>,
+    ) -> Result<(), LocatedError> {
+        self.in_body = true;
+        let (test, error) = self.read_test_line(result, test, span, text);
+        if let Some(e) = error {
+            self.report(e.at(span))?;
+        }
+
+        self.state = State::AfterTest;
+        self.statements.push(TapStatement::TestPoint(test));
+        self.test_seen += 1;
+        Ok(())
+    }
+
+    fn end_yaml(&mut self) {
+        if let State::Yaml = self.state {
+            self.statements
+                .last_mut()
+                .unwrap()
+                .set_yaml(std::mem::take(&mut self.yaml_accumulator), self.yaml_end);
+        }
+        self.state = State::Body;
+    }
+
+    pub(crate) fn read_line<'l>(
+        &mut self,
+        line: &'l str,
+        text: Text<'l, 'a>,
+    ) -> Result<(), LocatedError> {
+        let (line, line_len) = match line.strip_suffix('\n') {
+            Some(stripped) => (stripped.strip_suffix('\r').unwrap_or(stripped), line.len()),
+            None => (line, line.len() + 1),
+        };
+
+        let mut location = Location {
+            line: self.lines_read + 1,
+            start: self.offset,
+            end: self.offset + line.len(),
+        };
+        self.lines_read += 1;
+        self.offset += line_len;
+
+        let line = match &mut self.extractor {
+            None => line,
+            Some(extractor) => match extractor.strip(line) {
+                Some(stripped) => {
+                    location.start = location.end - stripped.len();
+                    stripped
+                }
+                None => return Ok(()),
+            },
+        };
+
+        if self.version.is_none() {
+            let Some(version) = read_version(line) else {
+                // Comments and stray output may precede the version line, only statements make
+                // the document a TAP 12 one
+                if starts_body(line) {
+                    self.version = Some(TapVersion::V12);
+                }
+                return self.read_body_line(line, location, text);
+            };
+
+            self.version = Some(match version {
+                Ok(version) => version,
+                Err(error) => {
+                    self.report(error.at(self.span(location)))?;
+                    TapVersion::V14
+                }
+            });
+
+            return Ok(());
+        }
+
+        if self.is_finished() {
+            return Ok(());
+        }
+
+        self.read_body_line(line, location, text)
+    }
+
+    ///
+    /// Returns true once the end of the document was read. All the lines fed afterwards are
+    /// ignored.
+    ///
+    pub fn is_finished(&self) -> bool {
+        self.version.is_some()
+            && (self.done
+                || (Some(self.test_seen) == self.test_expected
+                    && matches!(self.state, State::Body)))
+    }
+
+    /// Returns true if the document ends before `line`: either it is finished, or all its tests
+    /// were read and the line does not start the YAML block of the last one
+    pub(crate) fn ends_before(&self, line: &str) -> bool {
+        let line = line.trim_end_matches(['\n', '\r']);
+        self.is_finished()
+            || (matches!(self.state, State::AfterTest)
+                && Some(self.test_seen) == self.test_expected
+                && line != "  ---")
+    }
+
+    ///
+    /// Feeds a single line of the document to the parser. The first line is the version line,
+    /// unless the document follows TAP 12. Comments and lines that are not part of the TAP syntax
+    /// may precede the version line.
+    ///
+    /// The line may include its terminator. The byte offsets in the [spans](Span) are only exact if
+    /// lines are fed with their terminator, otherwise a single `\n` is assumed.
+    ///
+    /// Lines fed after the end of the document are ignored. Statements are made available through
+    /// [completed](Self::completed) as soon as no following line can modify them.
+    ///
+    pub fn feed_line(&mut self, line: &'a str) -> Result<(), LocatedError> {
+        self.read_line(line, Cow::Borrowed)
+    }
+
+    ///
+    /// Removes and returns the statements that are final, meaning that no line fed afterwards can
+    /// modify them.
+    ///
+    /// A test point is only final once the next line shows it is not followed by a YAML block.
+    ///
+    pub fn completed(&mut self) -> std::vec::Drain<'_, TapStatement<'a>> {
+        let end = match self.state {
+            State::AfterTest | State::Yaml if !self.is_finished() => {
+                self.statements.len().saturating_sub(1)
+            }
+            _ => self.statements.len(),
+        };
+
+        self.statements.drain(..end)
+    }
+
+    ///
+    /// Removes and returns the errors recorded by a [recovering](Self::recovering) parser.
+    ///
+    pub fn diagnostics(&mut self) -> std::vec::Drain<'_, LocatedError> {
+        self.diagnostics.drain(..)
+    }
+
+    ///
+    /// Signals the end of the input, and returns the statements that were not yet returned by
+    /// [completed](Self::completed).
+    ///
+    /// A YAML block or a subtest still open at the end of the input is an
+    /// [UnexpectedEOD](Error::UnexpectedEOD), a [recovering](Self::recovering) parser keeps the
+    /// statements read so far in them.
+    ///
+    pub fn finish(&mut self) -> Result<Vec<TapStatement<'a>>, LocatedError> {
+        let span = Span {
+            line: self.lines_read + 1,
+            start: self.offset,
+            end: self.offset,
+            depth: 0,
+        };
+
+        if self.version.is_none() {
+            self.report(Error::NoVersion.at(span))?;
+        } else if self.close_open() || !(self.done || self.read_plan) {
+            self.report(Error::UnexpectedEOD.at(span))?;
+        }
+
+        Ok(std::mem::take(&mut self.statements))
+    }
+
+    /// Ends the YAML block and the subtests left open at the end of the input, keeping their
+    /// statements. Returns true if any was open.
+    fn close_open(&mut self) -> bool {
+        match self.state {
+            State::Yaml | State::OrphanYaml => {
+                self.end_yaml();
+                true
+            }
+            State::Subtest => {
+                let mut sub_parser = self.sub_parser.take().unwrap();
+                sub_parser.parser.close_open();
+                let end = match sub_parser.parser.statements.last() {
+                    Some(statement) => statement.span().end,
+                    None => sub_parser.span.end,
+                };
+                let subtest = sub_parser.into_subtest(None, end);
+                self.statements.push(TapStatement::Subtest(subtest));
+                self.state = State::Body;
+                true
+            }
+            State::Body | State::AfterTest => false,
+        }
+    }
+
+    /// Signals the end of the input, and returns the whole document
+    pub(crate) fn finish_document(
+        &mut self,
+        trailing: Vec<Cow<'a, str>>,
+    ) -> Result<TapDocument<'a>, LocatedError> {
+        let statements = self.finish()?;
+        Ok(self.document(statements, trailing))
+    }
+
+    fn document(
+        &self,
+        statements: Vec<TapStatement<'a>>,
+        trailing: Vec<Cow<'a, str>>,
+    ) -> TapDocument<'a> {
+        TapDocument {
+            version: self.version.unwrap_or(TapVersion::V12),
+            statements,
+            trailing,
+        }
+    }
+
     ///
     /// This function allows you to extract the statements from a parser even if parsing failed.
     /// All the statements may not be completely parsed.
@@ -393,42 +1372,194 @@ impl<'a> TapParser<'a> {
     }
 
     ///
-    /// This function will reset the internal state of the TAP parser. It will parse a TAP
-    /// document into statements.
+    /// Gives back the statements of a document that are no longer needed, so that the next
+    /// documents are parsed into their allocation instead of allocating new buffers. The largest
+    /// YAML block of the test points is reused the same way. The subtests are dropped.
+    ///
+    /// Buffers are only taken back between documents, once the statements of the current one were
+    /// returned.
+    ///
+    /// ```rust
+    /// use tap_parser::TapParser;
+    ///
+    /// let inputs = ["TAP version 14\n1..1\nok 1\n", "TAP version 14\n1..2\nok 1\nok 2\n"];
+    /// let mut parser = TapParser::new();
+    /// for input in inputs {
+    ///     let document = parser.parse(input).unwrap();
+    ///     assert!(document.tests().all(|test| test.result));
+    ///     parser.recycle(document.statements);
+    /// }
+    /// ```
+    ///
+    pub fn recycle(&mut self, mut statements: Vec<TapStatement<'a>>) {
+        for statement in statements.drain(..) {
+            let yaml = match statement {
+                TapStatement::TestPoint(test) => test.yaml,
+                TapStatement::Subtest(TapSubDocument {
+                    ending: Some(ending),
+                    ..
+                }) => ending.yaml,
+                _ => continue,
+            };
+            if self.yaml_accumulator.is_empty()
+                && yaml.capacity() > self.yaml_accumulator.capacity()
+            {
+                self.yaml_accumulator = yaml;
+                self.yaml_accumulator.clear();
+            }
+        }
+
+        if self.statements.is_empty() && statements.capacity() > self.statements.capacity() {
+            self.statements = statements;
+        }
+    }
+
+    ///
+    /// Resets the parser to read a new document, as if it was just created, after a document
+    /// that was parsed completely or not.
+    ///
+    /// The configuration of the parser is kept: whether it [recovers](Self::recovering) from
+    /// errors, and the [prefix](Self::with_prefix) of the lines. The prefix learned by a parser
+    /// [extracting](Self::with_extraction) the document is forgotten. The buffers of the parser
+    /// keep their allocation, see [recycle](Self::recycle).
+    ///
+    /// [parse](Self::parse) resets the parser itself, this is only needed to reuse a parser fed
+    /// line by line.
+    ///
+    pub fn reset(&mut self) {
+        let Self {
+            depth: _,
+            recover: _,
+            strict,
+            extractor,
+            diagnostics,
+            lines_read,
+            offset,
+            version,
+            in_body,
+            done,
+            bailed,
+            state,
+            yaml_accumulator,
+            yaml_end,
+            statements,
+            read_plan,
+            sub_parser,
+            test_expected,
+            test_seen,
+        } = self;
+
+        *strict = false;
+        if let Some(extractor) = extractor {
+            extractor.reset();
+        }
+        diagnostics.clear();
+        *lines_read = 0;
+        *offset = 0;
+        *version = None;
+        *in_body = false;
+        *done = false;
+        *bailed = false;
+        *state = State::Body;
+        yaml_accumulator.clear();
+        *yaml_end = 0;
+        statements.clear();
+        *read_plan = false;
+        *sub_parser = None;
+        *test_expected = None;
+        *test_seen = 0;
+    }
+
+    ///
+    /// Resets the internal state of the TAP parser, see [reset](Self::reset), and parses a TAP
+    /// document into statements. The lines following the end of the document are kept in
+    /// [TapDocument::trailing].
     ///
     /// In case of errors you can access the previous statements with the
     /// [statements](Self::statements) method
     ///
-    pub fn parse(&mut self, input: &'a str) -> Result<Vec<TapStatement<'a>>, Error> {
-        let mut lines = input.lines();
-        let Some(first_line) = lines.next() else {
-            return Err(Error::NoVersion);
-        };
-
-        let Some(version) = first_line.strip_prefix("TAP version") else {
-            return Err(Error::NoVersion);
-        };
+    /// ```rust
+    /// use tap_parser::TapParser;
+    ///
+    /// let mut parser = TapParser::new();
+    /// assert!(parser.parse("TAP version 14\n1..1\n1..1\n").is_err());
+    ///
+    /// let document = parser.parse("TAP version 14\n1..1\nok 1\n").unwrap();
+    /// assert_eq!(document.statements.len(), 2);
+    /// ```
+    ///
+    pub fn parse(&mut self, input: &'a str) -> Result<TapDocument<'a>, LocatedError> {
+        self.reset();
 
-        if version.trim() != "14" {
-            return Err(Error::InvalidVersion(version.trim().to_string()));
+        let mut trailing = Vec::new();
+        for line in input.split_inclusive('\n') {
+            if self.is_finished() {
+                let line = line.strip_suffix('\n').unwrap_or(line);
+                trailing.push(Cow::Borrowed(line.strip_suffix('\r').unwrap_or(line)));
+            } else {
+                self.feed_line(line)?;
+            }
         }
 
-        for line in lines {
-            if self.done
-                || (Some(self.test_seen) == self.test_expected && matches!(self.state, State::Body))
-            {
-                break;
+        self.finish_document(trailing)
+    }
+
+    ///
+    /// Parses a whole TAP document, recovering from errors. See [recovering](Self::recovering).
+    ///
+    /// Returns the document with all the statements that could be parsed, along with all the
+    /// errors that were encountered, sorted by their position in the input.
+    ///
+    pub fn parse_recovering(&mut self, input: &'a str) -> (TapDocument<'a>, Vec<LocatedError>) {
+        let recover = std::mem::replace(&mut self.recover, true);
+        let document = self.parse(input);
+        self.recover = recover;
+
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                self.diagnostics.push(e);
+                let statements = std::mem::take(&mut self.statements);
+                self.document(statements, Vec::new())
             }
+        };
 
-            self.read_body_line(line)?;
-        }
+        (document, std::mem::take(&mut self.diagnostics))
+    }
+}
 
-        if !(self.done || self.read_plan) {
-            return Err(Error::UnexpectedEOD);
+/// Parses a `TAP version` or `KTAP version` line, returns `None` if the line is not a version line
+fn read_version(line: &str) -> Option<Result<TapVersion, Error>> {
+    let version = if let Some(version) = line.strip_prefix("TAP version") {
+        match version.trim() {
+            "12" => Ok(TapVersion::V12),
+            "13" => Ok(TapVersion::V13),
+            "14" => Ok(TapVersion::V14),
+            version => Err(version),
+        }
+    } else if let Some(version) = line.strip_prefix("KTAP version") {
+        match version.trim() {
+            "1" => Ok(TapVersion::KtapV1),
+            version => Err(version),
         }
+    } else {
+        return None;
+    };
 
-        Ok(std::mem::take(&mut self.statements))
-    }
+    Some(version.map_err(|version| Error::InvalidVersion(version.into())))
+}
+
+/// Returns true if the line is a statement of a TAP 12 document
+fn starts_body(line: &str) -> bool {
+    line.starts_with("1..")
+        || line.starts_with("ok")
+        || line.starts_with("not ok")
+        || line.starts_with("pragma ")
+        || (line.len() >= 9 && line.as_bytes()[0..9].eq_ignore_ascii_case(b"bail out!"))
+}
+
+fn is_subtest_header(line: &str) -> bool {
+    line.len() >= 9 && line.as_bytes()[0..9].eq_ignore_ascii_case(b"# subtest")
 }
 
 impl<'a> Default for TapParser<'a> {
@@ -0,0 +1,215 @@
+use std::{convert::Infallible, fmt::Display, str::FromStr};
+
+use crate::{TapStatement, TapSubDocument, TapTest};
+
+///
+/// Location of a test point inside nested subtests, such as `inner/deeper/3`.
+///
+/// Each segment is the name of a subtest, and the last one identifies the test in its
+/// document by its number, or by its description when it has none, or else by its position. A
+/// subtest without a name is identified like its ending. The ending of a subtest has the path of
+/// the subtest itself.
+///
+/// Paths are displayed with `/` between segments, escaping `/` and `\` in segments with a `\`,
+/// and can be parsed back from that form.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TestPath {
+    segments: Vec<String>,
+}
+
+impl TestPath {
+    ///
+    /// Returns the empty path, that of the document itself.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    pub fn push(&mut self, segment: impl Into<String>) {
+        self.segments.push(segment.into());
+    }
+
+    pub fn join(&self, segment: impl Into<String>) -> Self {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+
+    ///
+    /// Returns the path of the enclosing subtest, or `None` for the empty path.
+    ///
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.segments.split_last()?;
+        Some(Self {
+            segments: parent.to_vec(),
+        })
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for TestPath {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        Self {
+            segments: iter.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Display for TestPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, segment) in self.segments.iter().enumerate() {
+            if idx != 0 {
+                write!(f, "/")?;
+            }
+            for c in segment.chars() {
+                if c == '/' || c == '\\' {
+                    write!(f, "\\")?;
+                }
+                write!(f, "{c}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TestPath {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut path = TestPath::new();
+        if s.is_empty() {
+            return Ok(path);
+        }
+
+        let mut segment = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => segment.extend(chars.next().or(Some('\\'))),
+                '/' => path.push(std::mem::take(&mut segment)),
+                _ => segment.push(c),
+            }
+        }
+        path.push(segment);
+
+        Ok(path)
+    }
+}
+
+///
+/// Iterates over all the test points of a document and of its subtests, with their path.
+///
+/// Tests are yielded in the order of the document, the ending of a subtest after the tests of the
+/// subtest.
+///
+/// ```rust
+/// use tap_parser::{test_paths, TapParser};
+///
+/// let document = "TAP version 14\n1..2\n# Subtest: inner\n    1..1\n    ok 1\nok 1 - inner\nok 2\n";
+/// let statements = TapParser::new().parse(document).unwrap().statements;
+///
+/// let paths: Vec<_> = test_paths(&statements)
+///     .map(|(path, _)| path.to_string())
+///     .collect();
+/// assert_eq!(paths, ["inner/1", "inner", "2"]);
+/// ```
+///
+pub fn test_paths<'d>(statements: &'d [TapStatement<'d>]) -> TestPaths<'d> {
+    TestPaths {
+        stack: vec![Level {
+            statements: statements.iter(),
+            path: TestPath::new(),
+            position: 0,
+            ending: None,
+        }],
+    }
+}
+
+///
+/// Returns the first test point with the given path. See [TestPath].
+///
+pub fn find_test<'d>(
+    statements: &'d [TapStatement<'d>],
+    path: &TestPath,
+) -> Option<&'d TapTest<'d>> {
+    test_paths(statements)
+        .find(|(test_path, _)| test_path == path)
+        .map(|(_, test)| test)
+}
+
+impl<'a> TapSubDocument<'a> {
+    ///
+    /// Iterates over the test points of the subtest, with their path relative to the subtest.
+    /// See [test_paths].
+    ///
+    pub fn test_paths(&self) -> TestPaths<'_> {
+        test_paths(&self.body.statements)
+    }
+}
+
+///
+/// Iterator returned by [test_paths].
+///
+pub struct TestPaths<'d> {
+    stack: Vec<Level<'d>>,
+}
+
+struct Level<'d> {
+    statements: std::slice::Iter<'d, TapStatement<'d>>,
+    path: TestPath,
+    /// Number of test points read, used to identify the tests with neither number nor description
+    position: usize,
+    ending: Option<&'d TapTest<'d>>,
+}
+
+impl<'d> Iterator for TestPaths<'d> {
+    type Item = (TestPath, &'d TapTest<'d>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let level = self.stack.last_mut()?;
+            match level.statements.next() {
+                None => {
+                    let level = self.stack.pop().expect("The level is on the stack");
+                    if let Some(ending) = level.ending {
+                        return Some((level.path, ending));
+                    }
+                }
+                Some(TapStatement::TestPoint(test)) => {
+                    level.position += 1;
+                    let path = level.path.join(test_segment(test, level.position));
+                    return Some((path, test));
+                }
+                Some(TapStatement::Subtest(subtest)) => {
+                    level.position += 1;
+                    let segment = match (&subtest.name, &subtest.ending) {
+                        (Some(name), _) => name.to_string(),
+                        (None, Some(ending)) => test_segment(ending, level.position),
+                        (None, None) => level.position.to_string(),
+                    };
+                    let level = Level {
+                        statements: subtest.body.statements.iter(),
+                        path: level.path.join(segment),
+                        position: 0,
+                        ending: subtest.ending.as_ref(),
+                    };
+                    self.stack.push(level);
+                }
+                Some(_) => (),
+            }
+        }
+    }
+}
+
+fn test_segment(test: &TapTest<'_>, position: usize) -> String {
+    match (test.number, test.unescaped_desc()) {
+        (Some(number), _) => number.to_string(),
+        (None, Some(desc)) => desc.into_owned(),
+        (None, None) => position.to_string(),
+    }
+}
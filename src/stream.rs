@@ -0,0 +1,265 @@
+use std::{borrow::Cow, collections::VecDeque, io::BufRead};
+
+use crate::{LocatedError, ReadError, TapParser, TapStatement};
+
+///
+/// Parser for documents that are received piece by piece, for example from the output of a
+/// running test process.
+///
+/// Unlike [TapParser], the input does not need to outlive the parser: all the statements own
+/// their text. The input can be fed in arbitrary chunks with [feed](Self::feed), or line by line
+/// with [feed_line](Self::feed_line).
+///
+/// ```rust
+/// use tap_parser::TapStreamParser;
+///
+/// let mut parser = TapStreamParser::new();
+/// parser.feed("TAP version 14\n1..2\nok 1 - fir").unwrap();
+/// assert_eq!(parser.completed().count(), 1);
+///
+/// parser.feed("st\nok 2 - second\n").unwrap();
+/// assert_eq!(parser.completed().count(), 1);
+///
+/// assert_eq!(parser.finish().unwrap().len(), 1);
+/// ```
+///
+pub struct TapStreamParser {
+    parser: TapParser<'static>,
+    buffer: String,
+}
+
+fn owned(text: &str) -> Cow<'static, str> {
+    Cow::Owned(text.to_owned())
+}
+
+impl TapStreamParser {
+    pub fn new() -> Self {
+        Self {
+            parser: TapParser::new(),
+            buffer: String::new(),
+        }
+    }
+
+    ///
+    /// Creates a parser that recovers from errors. See [TapParser::recovering].
+    ///
+    pub fn recovering() -> Self {
+        Self {
+            parser: TapParser::recovering(),
+            buffer: String::new(),
+        }
+    }
+
+    ///
+    /// Feeds a chunk of the document. Chunks don't need to end on a line boundary, incomplete
+    /// lines are kept until the rest of the line is fed.
+    ///
+    pub fn feed(&mut self, chunk: &str) -> Result<(), LocatedError> {
+        let mut chunk = chunk;
+        while let Some(idx) = chunk.find('\n') {
+            let line = &chunk[..=idx];
+            chunk = &chunk[idx + 1..];
+
+            if self.buffer.is_empty() {
+                self.feed_line(line)?;
+            } else {
+                let mut buffer = std::mem::take(&mut self.buffer);
+                buffer.push_str(line);
+                let res = self.feed_line(&buffer);
+                buffer.clear();
+                self.buffer = buffer;
+                res?;
+            }
+        }
+
+        self.buffer.push_str(chunk);
+        Ok(())
+    }
+
+    ///
+    /// Feeds a complete line of the document. See [TapParser::feed_line] for the handling of line
+    /// terminators.
+    ///
+    pub fn feed_line(&mut self, line: &str) -> Result<(), LocatedError> {
+        self.parser.read_line(line, owned)
+    }
+
+    ///
+    /// Returns true once the end of the document was read. See [TapParser::is_finished].
+    ///
+    pub fn is_finished(&self) -> bool {
+        self.parser.is_finished()
+    }
+
+    ///
+    /// Removes and returns the statements that are final. See [TapParser::completed].
+    ///
+    pub fn completed(&mut self) -> std::vec::Drain<'_, TapStatement<'static>> {
+        self.parser.completed()
+    }
+
+    ///
+    /// Removes and returns the errors recorded by a [recovering](Self::recovering) parser.
+    ///
+    pub fn diagnostics(&mut self) -> std::vec::Drain<'_, LocatedError> {
+        self.parser.diagnostics()
+    }
+
+    ///
+    /// Signals the end of the input, and returns the statements that were not yet returned by
+    /// [completed](Self::completed).
+    ///
+    pub fn finish(&mut self) -> Result<Vec<TapStatement<'static>>, LocatedError> {
+        if !self.buffer.is_empty() {
+            let buffer = std::mem::take(&mut self.buffer);
+            self.feed_line(&buffer)?;
+        }
+
+        self.parser.finish()
+    }
+
+    ///
+    /// This function allows you to extract the statements from a parser even if parsing failed.
+    /// All the statements may not be completely parsed.
+    ///
+    pub fn statements(self) -> Vec<TapStatement<'static>> {
+        self.parser.statements()
+    }
+
+    ///
+    /// Gives back the statements of a document that are no longer needed, to reuse their
+    /// allocation. See [TapParser::recycle].
+    ///
+    pub fn recycle(&mut self, statements: Vec<TapStatement<'static>>) {
+        self.parser.recycle(statements);
+    }
+
+    ///
+    /// Resets the parser to read a new document, dropping the incomplete line that was fed. See
+    /// [TapParser::reset].
+    ///
+    pub fn reset(&mut self) {
+        self.parser.reset();
+        self.buffer.clear();
+    }
+}
+
+impl From<TapParser<'static>> for TapStreamParser {
+    fn from(parser: TapParser<'static>) -> Self {
+        Self {
+            parser,
+            buffer: String::new(),
+        }
+    }
+}
+
+impl Default for TapStreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Iterator over the statements of a document read from a [BufRead].
+///
+/// Statements are yielded as soon as they are final, so the whole document never needs to be
+/// held in memory. Reading stops at the end of the TAP document, trailing lines are never read.
+/// After an error is returned the iterator is exhausted.
+///
+/// ```rust
+/// use tap_parser::TapReader;
+///
+/// let input = "TAP version 14\n1..1\nok 1 - success\n".as_bytes();
+/// let statements = TapReader::new(input).collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(statements.len(), 2);
+/// ```
+///
+pub struct TapReader<R> {
+    reader: R,
+    parser: TapStreamParser,
+    line: String,
+    pending: VecDeque<TapStatement<'static>>,
+    done: bool,
+}
+
+impl<R: BufRead> TapReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_parser(reader, TapStreamParser::new())
+    }
+
+    ///
+    /// Creates a reader that recovers from errors. See [TapParser::recovering].
+    ///
+    /// The errors are not yielded by the iterator, they can be retrieved with
+    /// [diagnostics](Self::diagnostics).
+    ///
+    pub fn recovering(reader: R) -> Self {
+        Self::with_parser(reader, TapStreamParser::recovering())
+    }
+
+    ///
+    /// Creates a reader using a configured parser, for example one that
+    /// [extracts](TapParser::with_extraction) the document from log output.
+    ///
+    /// ```rust
+    /// use tap_parser::{TapParser, TapReader};
+    ///
+    /// let input = "12:00 TAP version 14\n12:01 1..1\n12:01 ok 1 - success\n".as_bytes();
+    /// let parser = TapParser::new().with_extraction().into();
+    /// let statements = TapReader::with_parser(input, parser)
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(statements.len(), 2);
+    /// ```
+    ///
+    pub fn with_parser(reader: R, parser: TapStreamParser) -> Self {
+        Self {
+            reader,
+            parser,
+            line: String::new(),
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    ///
+    /// Removes and returns the errors recorded by a [recovering](Self::recovering) reader.
+    ///
+    pub fn diagnostics(&mut self) -> std::vec::Drain<'_, LocatedError> {
+        self.parser.diagnostics()
+    }
+
+    fn read_next(&mut self) -> Result<(), ReadError> {
+        self.line.clear();
+        if self.parser.is_finished() || self.reader.read_line(&mut self.line)? == 0 {
+            self.done = true;
+            self.pending.extend(self.parser.finish()?);
+        } else {
+            self.parser.feed_line(&self.line)?;
+            self.pending.extend(self.parser.completed());
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for TapReader<R> {
+    type Item = Result<TapStatement<'static>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(statement) = self.pending.pop_front() {
+                return Some(Ok(statement));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            if let Err(e) = self.read_next() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{DirectiveKind, TapStatement, TapSubDocument, TapTest};
+
+///
+/// Statistics of the test points of a document, with a breakdown for each subtest.
+///
+/// The counts only include the test points of the document itself: a subtest is counted once,
+/// through its ending. The tests of the subtest are counted in its own summary, in
+/// [subtests](Self::subtests).
+///
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct TapSummary<'s> {
+    /// Name of the subtest, `None` for the top-level document
+    pub name: Option<&'s str>,
+    /// Test count announced by the plan
+    pub planned: Option<usize>,
+    /// `ok` tests without directive
+    pub passed: usize,
+    /// `not ok` tests without directive
+    pub failed: usize,
+    /// Tests with a `SKIP` directive
+    pub skipped: usize,
+    /// `not ok` tests with a `TODO` directive
+    pub todo: usize,
+    /// `ok` tests with a `TODO` directive, that were expected to fail
+    pub todo_passed: usize,
+    /// True if the document was interrupted by a `Bail out!`, in the document or in a subtest
+    pub bailed: bool,
+    pub subtests: Vec<TapSummary<'s>>,
+}
+
+impl<'s> TapSummary<'s> {
+    pub fn new<'a: 's>(statements: &'s [TapStatement<'a>]) -> Self {
+        let mut summary = Self::default();
+
+        for statement in statements {
+            match statement {
+                TapStatement::Plan(plan) => {
+                    summary.planned.get_or_insert(plan.count);
+                }
+                TapStatement::TestPoint(test) => summary.add(test),
+                TapStatement::Subtest(subtest) => {
+                    if let Some(ending) = &subtest.ending {
+                        summary.add(ending);
+                    }
+                    let subtest = subtest.summary();
+                    summary.bailed |= subtest.bailed;
+                    summary.subtests.push(subtest);
+                }
+                TapStatement::BailOut(_) => summary.bailed = true,
+                TapStatement::Comment(_) | TapStatement::Pragma(_) | TapStatement::Unknown(_) => {}
+            }
+        }
+
+        summary
+    }
+
+    fn add(&mut self, test: &TapTest) {
+        let counter = match (&test.directive, test.result) {
+            (None, true) => &mut self.passed,
+            (None, false) => &mut self.failed,
+            (Some(d), _) if d.kind == DirectiveKind::Skip => &mut self.skipped,
+            (Some(_), true) => &mut self.todo_passed,
+            (Some(_), false) => &mut self.todo,
+        };
+        *counter += 1;
+    }
+
+    ///
+    /// Number of test points in the document.
+    ///
+    pub fn total(&self) -> usize {
+        self.passed + self.failed + self.skipped + self.todo + self.todo_passed
+    }
+
+    ///
+    /// Returns true if the document passed, following the TAP 14 semantics: no test failed,
+    /// ignoring the failures of `TODO` tests, the test count matches the plan, and the run did
+    /// not bail out.
+    ///
+    pub fn is_success(&self) -> bool {
+        self.failed == 0 && !self.bailed && self.planned == Some(self.total())
+    }
+}
+
+impl<'a> TapSubDocument<'a> {
+    ///
+    /// Computes the statistics of the subtest. See [TapSummary].
+    ///
+    pub fn summary(&self) -> TapSummary<'_> {
+        TapSummary {
+            name: self.name.as_deref(),
+            ..TapSummary::new(&self.body.statements)
+        }
+    }
+}
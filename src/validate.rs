@@ -0,0 +1,172 @@
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+
+use crate::{Span, TapPlan, TapStatement, TapSubDocument};
+
+///
+/// Inconsistency found in a document that was parsed successfully.
+///
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Clone)]
+pub enum FindingKind {
+    #[error("Plan expects {planned} tests, found {count}")]
+    PlanMismatch { planned: usize, count: usize },
+    #[error("Test number {number} was already used")]
+    DuplicateNumber { number: usize },
+    #[error("Test number {number} is out of order, expected {expected}")]
+    OutOfOrder { expected: usize, number: usize },
+    #[error("{}", missing_message(*.first, *.last))]
+    MissingNumbers { first: usize, last: usize },
+    #[error("Subtest ending is `{}` but its tests {}", if *.ending { "ok" } else { "not ok" }, if *.passed { "passed" } else { "failed" })]
+    EndingMismatch { ending: bool, passed: bool },
+}
+
+///
+/// A [FindingKind] along with the location of the statement it concerns.
+///
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Clone)]
+#[error("Line {}: {kind}", .span.line)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub span: Span,
+}
+
+///
+/// Checks the consistency of the statements of a document, and of all its subtests.
+///
+/// The number of tests must match the plan, and the test numbers must be sequential. Subtest
+/// endings must agree with the result of the subtest, that fails if one of its tests failed or if
+/// it does not follow its plan. A document interrupted by a `Bail out!`, in the document or in
+/// one of its subtests, is not checked against its plan.
+///
+pub fn validate(statements: &[TapStatement<'_>]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    validate_statements(statements, &mut findings);
+    findings
+}
+
+impl<'a> TapSubDocument<'a> {
+    ///
+    /// Checks the consistency of the subtest. See [validate].
+    ///
+    pub fn validate(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        validate_subtest(self, &mut findings);
+        findings
+    }
+}
+
+fn validate_subtest(subtest: &TapSubDocument<'_>, findings: &mut Vec<Finding>) {
+    validate_statements(&subtest.body.statements, findings);
+
+    if let Some(ending) = &subtest.ending {
+        // The subtest also fails if its plan is not followed, see TapSummary::is_success
+        let passed = subtest.summary().is_success();
+        if ending.result != passed {
+            findings.push(Finding {
+                kind: FindingKind::EndingMismatch {
+                    ending: ending.result,
+                    passed,
+                },
+                span: ending.span,
+            });
+        }
+    }
+}
+
+fn validate_statements(statements: &[TapStatement<'_>], findings: &mut Vec<Finding>) {
+    let mut plan: Option<&TapPlan> = None;
+    let mut bailed = false;
+    let mut count = 0;
+    let mut numbers = BTreeMap::new();
+    // Tests without numbers implicitly use the number of their position
+    let mut implicit = BTreeSet::new();
+
+    for statement in statements {
+        let test = match statement {
+            TapStatement::Plan(p) => {
+                plan.get_or_insert(p);
+                continue;
+            }
+            TapStatement::BailOut(_) => {
+                bailed = true;
+                continue;
+            }
+            TapStatement::TestPoint(t) => t,
+            TapStatement::Subtest(s) => {
+                validate_subtest(s, findings);
+                bailed |= s.body.is_bailed();
+                match &s.ending {
+                    Some(ending) => ending,
+                    None => continue,
+                }
+            }
+            TapStatement::Comment(_) | TapStatement::Pragma(_) | TapStatement::Unknown(_) => {
+                continue
+            }
+        };
+
+        count += 1;
+        let Some(number) = test.number else {
+            implicit.insert(count);
+            continue;
+        };
+
+        let kind = match numbers.entry(number) {
+            Entry::Occupied(_) => FindingKind::DuplicateNumber { number },
+            Entry::Vacant(entry) => {
+                entry.insert(test.span);
+                if number == count {
+                    continue;
+                }
+                FindingKind::OutOfOrder {
+                    expected: count,
+                    number,
+                }
+            }
+        };
+        findings.push(Finding {
+            kind,
+            span: test.span,
+        });
+    }
+
+    // Gaps are reported on the first test following them, a gap of several numbers as a range
+    let mut next = 1;
+    for (&number, &span) in &numbers {
+        let mut first = next;
+        while first < number {
+            if implicit.contains(&first) {
+                first += 1;
+                continue;
+            }
+            let last = implicit
+                .range(first..number)
+                .next()
+                .map_or(number - 1, |&implicit| implicit - 1);
+            findings.push(Finding {
+                kind: FindingKind::MissingNumbers { first, last },
+                span,
+            });
+            first = last + 1;
+        }
+        next = number.saturating_add(1);
+    }
+
+    match plan {
+        Some(plan) if !bailed && plan.count != count => findings.push(Finding {
+            kind: FindingKind::PlanMismatch {
+                planned: plan.count,
+                count,
+            },
+            span: plan.span,
+        }),
+        _ => (),
+    }
+}
+
+fn missing_message(first: usize, last: usize) -> String {
+    if first == last {
+        format!("Test number {first} is missing")
+    } else {
+        format!("Test numbers {first} to {last} are missing")
+    }
+}
@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+
+use crate::{
+    TapBailOut, TapComment, TapPlan, TapPragma, TapStatement, TapSubDocument, TapTest, TapUnknown,
+};
+
+///
+/// Walks the statements of a document, including the statements of its subtests.
+///
+/// All the methods do nothing by default, except [visit_statement](Self::visit_statement) that
+/// dispatches the statement to the other methods, see [walk_statement]. A subtest is visited by
+/// [enter_subtest](Self::enter_subtest), its statements, [exit_subtest](Self::exit_subtest), and
+/// finally its ending as a test point of the enclosing document.
+///
+/// ```rust
+/// use tap_parser::{walk, TapParser, TapTest, Visitor};
+///
+/// #[derive(Default)]
+/// struct Failures<'d>(Vec<&'d str>);
+///
+/// impl<'d> Visitor<'d> for Failures<'d> {
+///     fn visit_test(&mut self, test: &'d TapTest<'d>) {
+///         if !test.result {
+///             self.0.extend(test.desc.as_deref());
+///         }
+///     }
+/// }
+///
+/// let document = "TAP version 14\n1..2\n# Subtest: inner\n    1..1\n    not ok 1 - deep\nnot ok 1 - inner\nok 2\n";
+/// let statements = TapParser::new().parse(document).unwrap().statements;
+///
+/// let mut failures = Failures::default();
+/// walk(&mut failures, &statements);
+/// assert_eq!(failures.0, ["deep", "inner"]);
+/// ```
+///
+pub trait Visitor<'d> {
+    fn visit_statement(&mut self, statement: &'d TapStatement<'d>) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_plan(&mut self, _plan: &'d TapPlan<'d>) {}
+
+    fn visit_test(&mut self, _test: &'d TapTest<'d>) {}
+
+    /// Called after [visit_test](Self::visit_test) for tests followed by a YAML block
+    fn visit_yaml(&mut self, _test: &'d TapTest<'d>, _yaml: &'d [Cow<'d, str>]) {}
+
+    fn visit_comment(&mut self, _comment: &'d TapComment<'d>) {}
+
+    fn visit_pragma(&mut self, _pragma: &'d TapPragma<'d>) {}
+
+    fn visit_unknown(&mut self, _unknown: &'d TapUnknown<'d>) {}
+
+    fn visit_bail_out(&mut self, _bail_out: &'d TapBailOut<'d>) {}
+
+    fn enter_subtest(&mut self, _subtest: &'d TapSubDocument<'d>) {}
+
+    fn exit_subtest(&mut self, _subtest: &'d TapSubDocument<'d>) {}
+}
+
+///
+/// Visits all the statements, in order. See [Visitor].
+///
+pub fn walk<'d, V: Visitor<'d> + ?Sized>(visitor: &mut V, statements: &'d [TapStatement<'d>]) {
+    for statement in statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+///
+/// Dispatches a statement to the method of the visitor handling it, and walks the statements of
+/// subtests.
+///
+pub fn walk_statement<'d, V: Visitor<'d> + ?Sized>(
+    visitor: &mut V,
+    statement: &'d TapStatement<'d>,
+) {
+    match statement {
+        TapStatement::Plan(plan) => visitor.visit_plan(plan),
+        TapStatement::TestPoint(test) => walk_test(visitor, test),
+        TapStatement::Comment(comment) => visitor.visit_comment(comment),
+        TapStatement::Pragma(pragma) => visitor.visit_pragma(pragma),
+        TapStatement::Unknown(unknown) => visitor.visit_unknown(unknown),
+        TapStatement::BailOut(bail_out) => visitor.visit_bail_out(bail_out),
+        TapStatement::Subtest(subtest) => {
+            visitor.enter_subtest(subtest);
+            walk(visitor, &subtest.body.statements);
+            visitor.exit_subtest(subtest);
+            if let Some(ending) = &subtest.ending {
+                walk_test(visitor, ending);
+            }
+        }
+    }
+}
+
+fn walk_test<'d, V: Visitor<'d> + ?Sized>(visitor: &mut V, test: &'d TapTest<'d>) {
+    visitor.visit_test(test);
+    if !test.yaml.is_empty() {
+        visitor.visit_yaml(test, &test.yaml);
+    }
+}
+
+///
+/// Mutable variant of [Visitor], allowing to modify the statements while walking them.
+///
+pub trait VisitorMut<'a> {
+    fn visit_statement(&mut self, statement: &mut TapStatement<'a>) {
+        walk_statement_mut(self, statement)
+    }
+
+    fn visit_plan(&mut self, _plan: &mut TapPlan<'a>) {}
+
+    fn visit_test(&mut self, _test: &mut TapTest<'a>) {}
+
+    /// Called after [visit_test](Self::visit_test) for tests followed by a YAML block
+    fn visit_yaml(&mut self, _yaml: &mut Vec<Cow<'a, str>>) {}
+
+    fn visit_comment(&mut self, _comment: &mut TapComment<'a>) {}
+
+    fn visit_pragma(&mut self, _pragma: &mut TapPragma<'a>) {}
+
+    fn visit_unknown(&mut self, _unknown: &mut TapUnknown<'a>) {}
+
+    fn visit_bail_out(&mut self, _bail_out: &mut TapBailOut<'a>) {}
+
+    fn enter_subtest(&mut self, _subtest: &mut TapSubDocument<'a>) {}
+
+    fn exit_subtest(&mut self, _subtest: &mut TapSubDocument<'a>) {}
+}
+
+///
+/// Visits all the statements, in order. See [VisitorMut].
+///
+pub fn walk_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    statements: &mut [TapStatement<'a>],
+) {
+    for statement in statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+///
+/// Mutable variant of [walk_statement].
+///
+pub fn walk_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    statement: &mut TapStatement<'a>,
+) {
+    match statement {
+        TapStatement::Plan(plan) => visitor.visit_plan(plan),
+        TapStatement::TestPoint(test) => walk_test_mut(visitor, test),
+        TapStatement::Comment(comment) => visitor.visit_comment(comment),
+        TapStatement::Pragma(pragma) => visitor.visit_pragma(pragma),
+        TapStatement::Unknown(unknown) => visitor.visit_unknown(unknown),
+        TapStatement::BailOut(bail_out) => visitor.visit_bail_out(bail_out),
+        TapStatement::Subtest(subtest) => {
+            visitor.enter_subtest(subtest);
+            walk_mut(visitor, &mut subtest.body.statements);
+            visitor.exit_subtest(subtest);
+            if let Some(ending) = &mut subtest.ending {
+                walk_test_mut(visitor, ending);
+            }
+        }
+    }
+}
+
+fn walk_test_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, test: &mut TapTest<'a>) {
+    visitor.visit_test(test);
+    if !test.yaml.is_empty() {
+        visitor.visit_yaml(&mut test.yaml);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{borrow::Cow, io::Write};
+
+use crate::{DirectiveKind, TapStatement, TapSubDocument, TapTest};
+
+///
+/// Writes statements as a TAP 14 document.
+///
+/// Parsing the output gives back the written statements, except for their [spans](crate::Span).
+///
+/// ```rust
+/// use tap_parser::{TapParser, TapWriter};
+///
+/// let document = TapParser::new().parse("TAP version 14\n1..1\nok 1 -   success\n").unwrap();
+///
+/// let mut writer = TapWriter::new(Vec::new());
+/// writer.write_document(&document.statements).unwrap();
+/// let output = String::from_utf8(writer.into_inner()).unwrap();
+/// assert_eq!(output, "TAP version 14\n1..1\nok 1 - success\n");
+/// ```
+///
+pub struct TapWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> TapWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    ///
+    /// Writes the version line, that must precede the statements.
+    ///
+    pub fn write_version(&mut self) -> std::io::Result<()> {
+        writeln!(self.writer, "TAP version 14")
+    }
+
+    ///
+    /// Writes the version line followed by all the statements.
+    ///
+    pub fn write_document(&mut self, statements: &[TapStatement<'_>]) -> std::io::Result<()> {
+        self.write_version()?;
+        for statement in statements {
+            self.write_statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_statement(&mut self, statement: &TapStatement<'_>) -> std::io::Result<()> {
+        self.write_indented(statement, 0)
+    }
+
+    fn write_indented(
+        &mut self,
+        statement: &TapStatement<'_>,
+        depth: usize,
+    ) -> std::io::Result<()> {
+        let indent = "    ".repeat(depth);
+
+        match statement {
+            TapStatement::Plan(plan) => {
+                write!(self.writer, "{indent}1..{}", plan.count)?;
+                if let Some(reason) = &plan.reason {
+                    write!(self.writer, " # {reason}")?;
+                }
+                writeln!(self.writer)
+            }
+            TapStatement::TestPoint(test) => self.write_test(test, &indent),
+            TapStatement::Comment(comment) => writeln!(self.writer, "{indent}# {}", comment.text),
+            TapStatement::Subtest(subtest) => self.write_subtest(subtest, depth),
+            TapStatement::Pragma(pragma) => {
+                let sign = if pragma.enabled { '+' } else { '-' };
+                writeln!(self.writer, "{indent}pragma {sign}{}", pragma.key)
+            }
+            TapStatement::Unknown(unknown) => writeln!(self.writer, "{indent}{}", unknown.text),
+            TapStatement::BailOut(bail_out) => {
+                write!(self.writer, "{indent}Bail out!")?;
+                if let Some(reason) = &bail_out.reason {
+                    write!(self.writer, " {reason}")?;
+                }
+                writeln!(self.writer)
+            }
+        }
+    }
+
+    fn write_test(&mut self, test: &TapTest<'_>, indent: &str) -> std::io::Result<()> {
+        let result = if test.result { "ok" } else { "not ok" };
+        write!(self.writer, "{indent}{result}")?;
+
+        if let Some(number) = test.number {
+            write!(self.writer, " {number}")?;
+        }
+
+        if let Some(desc) = &test.desc {
+            let desc = escape_desc(desc);
+            write!(self.writer, " - {desc}")?;
+        }
+
+        if let Some(directive) = &test.directive {
+            let kind = match directive.kind {
+                DirectiveKind::Skip => "SKIP",
+                DirectiveKind::Todo => "TODO",
+            };
+            write!(self.writer, " # {kind}")?;
+            if let Some(reason) = &directive.reason {
+                write!(self.writer, " {reason}")?;
+            }
+        }
+        writeln!(self.writer)?;
+
+        if !test.yaml.is_empty() {
+            writeln!(self.writer, "{indent}  ---")?;
+            for line in &test.yaml {
+                writeln!(self.writer, "{indent}  {line}")?;
+            }
+            writeln!(self.writer, "{indent}  ...")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_subtest(&mut self, subtest: &TapSubDocument<'_>, depth: usize) -> std::io::Result<()> {
+        let indent = "    ".repeat(depth);
+        match &subtest.name {
+            Some(name) => writeln!(self.writer, "{indent}# Subtest: {name}")?,
+            None => writeln!(self.writer, "{indent}# Subtest")?,
+        }
+
+        for statement in &subtest.body.statements {
+            self.write_indented(statement, depth + 1)?;
+        }
+
+        match &subtest.ending {
+            Some(ending) => self.write_test(ending, &indent),
+            None => Ok(()),
+        }
+    }
+}
+
+///
+/// Writes statements as a TAP 14 document to a string. See [TapWriter].
+///
+pub fn write(statements: &[TapStatement<'_>]) -> String {
+    let mut writer = TapWriter::new(Vec::new());
+    writer
+        .write_document(statements)
+        .expect("Writing to a Vec can't fail");
+    String::from_utf8(writer.into_inner()).expect("The document is valid UTF-8")
+}
+
+/// Escapes the `#` that the parser would read as the start of a directive. Descriptions read by
+/// the parser are returned unchanged.
+fn escape_desc(desc: &str) -> Cow<'_, str> {
+    let mut escaped = false;
+    let mut output = String::new();
+    let mut copied = 0;
+
+    // Mirrors the directive lookup of the parser
+    for (idx, c) in desc.bytes().enumerate() {
+        match c {
+            b'\\' => escaped = !escaped,
+            b'#' if !escaped => {
+                output.push_str(&desc[copied..idx]);
+                output.push('\\');
+                copied = idx;
+            }
+            _ => escaped = false,
+        }
+    }
+
+    if output.is_empty() {
+        Cow::Borrowed(desc)
+    } else {
+        output.push_str(&desc[copied..]);
+        Cow::Owned(output)
+    }
+}
@@ -0,0 +1,96 @@
+use serde_yaml::Value;
+
+use crate::{Error, LocatedError, TapTest};
+
+///
+/// Structured content of the YAML block following a test point.
+///
+/// The accessors for the common TAP diagnostic keys return `None` when the key is missing, or
+/// when its value does not have the expected type.
+///
+#[derive(PartialEq, Debug, Clone)]
+pub struct YamlBlock {
+    value: Value,
+}
+
+impl YamlBlock {
+    ///
+    /// Parses the lines of a YAML block, as stored in [TapTest::yaml].
+    ///
+    pub fn parse<S: AsRef<str>>(lines: &[S]) -> Result<Self, Error> {
+        let mut document = String::new();
+        for line in lines {
+            document += line.as_ref();
+            document += "\n";
+        }
+
+        serde_yaml::from_str(&document)
+            .map(|value| Self { value })
+            .map_err(|e| Error::InvalidYamlBlock(e.to_string()))
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+
+    ///
+    /// Returns the value associated with `key`, if the block is a mapping.
+    ///
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.value.as_mapping()?.get(key)
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.get("message")?.as_str()
+    }
+
+    pub fn severity(&self) -> Option<&str> {
+        self.get("severity")?.as_str()
+    }
+
+    pub fn data(&self) -> Option<&Value> {
+        self.get("data")
+    }
+
+    ///
+    /// Returns the `got` value, either at the top-level of the block or inside `data`.
+    ///
+    pub fn got(&self) -> Option<&Value> {
+        self.get("got").or_else(|| self.data()?.get("got"))
+    }
+
+    ///
+    /// Returns the `expected` value, either at the top-level of the block or inside `data`.
+    ///
+    pub fn expected(&self) -> Option<&Value> {
+        self.get("expected")
+            .or_else(|| self.data()?.get("expected"))
+    }
+
+    pub fn at(&self) -> Option<&Value> {
+        self.get("at")
+    }
+
+    pub fn duration_ms(&self) -> Option<f64> {
+        self.get("duration_ms")?.as_f64()
+    }
+}
+
+impl<'a> TapTest<'a> {
+    ///
+    /// Parses the YAML block of this test point, returns `None` if there is no YAML block.
+    ///
+    pub fn yaml_block(&self) -> Result<Option<YamlBlock>, LocatedError> {
+        if self.yaml.is_empty() {
+            return Ok(None);
+        }
+
+        YamlBlock::parse(&self.yaml)
+            .map(Some)
+            .map_err(|e| e.at(self.span))
+    }
+}